@@ -0,0 +1,3 @@
+DROP INDEX IF EXISTS idx_messages_inserted_at;
+
+ALTER TABLE messages DROP COLUMN IF EXISTS inserted_at;
@@ -0,0 +1,3 @@
+ALTER TABLE messages ADD COLUMN inserted_at TIMESTAMPTZ NOT NULL DEFAULT now();
+
+CREATE INDEX idx_messages_inserted_at ON messages(inserted_at);
@@ -0,0 +1,5 @@
+ALTER TABLE messages ADD COLUMN bundle_size INTEGER;
+
+UPDATE messages SET bundle_size = octet_length(bundle);
+
+ALTER TABLE messages ALTER COLUMN bundle_size SET NOT NULL;
@@ -0,0 +1,23 @@
+CREATE TABLE message_tags (
+  row_id SERIAL PRIMARY KEY,
+  message_row_id INTEGER NOT NULL REFERENCES messages(row_id) ON DELETE CASCADE,
+  name TEXT NOT NULL,
+  value TEXT NOT NULL
+);
+
+CREATE INDEX idx_message_tags_name_value ON message_tags (name, value);
+
+CREATE INDEX idx_message_tags_message_row_id ON message_tags (message_row_id);
+
+-- both the old and current message_data shapes keep the tags at message.tags
+INSERT INTO message_tags (message_row_id, name, value)
+SELECT m.row_id, t->>'name', t->>'value'
+FROM messages m
+CROSS JOIN LATERAL jsonb_array_elements(
+  CASE
+    WHEN jsonb_typeof(m.message_data->'message'->'tags') = 'array'
+    THEN m.message_data->'message'->'tags'
+    ELSE '[]'::jsonb
+  END
+) AS t
+WHERE t->>'name' IS NOT NULL AND t->>'value' IS NOT NULL;
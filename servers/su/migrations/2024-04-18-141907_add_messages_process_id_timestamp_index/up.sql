@@ -0,0 +1,3 @@
+-- get_messages filters on process_id and orders by timestamp,
+-- with this index the rows come back already sorted
+CREATE INDEX IF NOT EXISTS idx_messages_process_id_timestamp ON messages (process_id, "timestamp");
@@ -0,0 +1 @@
+CREATE INDEX IF NOT EXISTS idx_messages_process_id_hash_chain ON messages (process_id, hash_chain);
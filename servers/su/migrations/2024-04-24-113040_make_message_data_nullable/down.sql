@@ -0,0 +1,2 @@
+-- rows saved without message_data can't be restored here, they have to be removed first
+ALTER TABLE messages ALTER COLUMN message_data SET NOT NULL;
@@ -0,0 +1 @@
+ALTER TABLE messages ALTER COLUMN message_data DROP NOT NULL;
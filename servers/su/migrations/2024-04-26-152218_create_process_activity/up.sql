@@ -0,0 +1,11 @@
+CREATE TABLE process_activity (
+  process_id VARCHAR PRIMARY KEY,
+  last_timestamp BIGINT NOT NULL
+);
+
+CREATE INDEX idx_process_activity_last_timestamp ON process_activity (last_timestamp);
+
+INSERT INTO process_activity (process_id, last_timestamp)
+SELECT process_id, MAX(timestamp)
+FROM messages
+GROUP BY process_id;
@@ -0,0 +1,9 @@
+-- put the bytes back before the bundles table goes
+UPDATE messages m SET bundle = b.bundle
+FROM bundles b
+WHERE m.bundle IS NULL AND m.bundle_hash = b.hash;
+
+ALTER TABLE messages ALTER COLUMN bundle SET NOT NULL;
+DROP INDEX IF EXISTS idx_messages_bundle_hash;
+ALTER TABLE messages DROP COLUMN bundle_hash;
+DROP TABLE IF EXISTS bundles;
@@ -0,0 +1,10 @@
+CREATE TABLE bundles (
+  hash VARCHAR PRIMARY KEY,
+  bundle BYTEA NOT NULL
+);
+
+-- rows saved with BUNDLE_TABLE set keep their bytes in bundles
+ALTER TABLE messages ADD COLUMN bundle_hash VARCHAR REFERENCES bundles(hash);
+ALTER TABLE messages ALTER COLUMN bundle DROP NOT NULL;
+
+CREATE INDEX idx_messages_bundle_hash ON messages (bundle_hash) WHERE bundle_hash IS NOT NULL;
@@ -0,0 +1 @@
+DROP INDEX IF EXISTS idx_messages_process_id_epoch_nonce;
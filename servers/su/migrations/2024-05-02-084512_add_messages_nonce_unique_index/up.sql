@@ -0,0 +1,20 @@
+-- the race this index closes may already have stored two messages on a nonce,
+-- which one to keep is for an operator to decide so refuse and name them
+DO $$
+DECLARE
+  duplicates TEXT;
+BEGIN
+  SELECT string_agg(format('%s epoch %s nonce %s', process_id, epoch, nonce), ', ')
+  INTO duplicates
+  FROM (
+    SELECT process_id, epoch, nonce FROM messages
+    GROUP BY process_id, epoch, nonce HAVING COUNT(*) > 1
+    ORDER BY process_id, epoch, nonce LIMIT 20
+  ) d;
+  IF duplicates IS NOT NULL THEN
+    RAISE EXCEPTION 'messages share a nonce, remove the duplicates before migrating: %', duplicates;
+  END IF;
+END $$;
+
+-- one message per nonce of a process, save_message inserts with ON CONFLICT on it
+CREATE UNIQUE INDEX IF NOT EXISTS idx_messages_process_id_epoch_nonce ON messages (process_id, epoch, nonce);
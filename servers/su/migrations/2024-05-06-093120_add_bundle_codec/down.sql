@@ -0,0 +1,11 @@
+-- postgres can't decompress zstd, refuse rather than leave unreadable bundles
+DO $$
+BEGIN
+  IF EXISTS (SELECT 1 FROM messages WHERE bundle_codec <> 0)
+     OR EXISTS (SELECT 1 FROM bundles WHERE codec <> 0) THEN
+    RAISE EXCEPTION 'compressed bundles are still stored, decompress them before reverting';
+  END IF;
+END $$;
+
+ALTER TABLE bundles DROP COLUMN codec;
+ALTER TABLE messages DROP COLUMN bundle_codec;
@@ -0,0 +1,3 @@
+-- 0 is the raw bundle, 1 zstd compressed, see COMPRESS_BUNDLES
+ALTER TABLE messages ADD COLUMN bundle_codec SMALLINT NOT NULL DEFAULT 0;
+ALTER TABLE bundles ADD COLUMN codec SMALLINT NOT NULL DEFAULT 0;
@@ -0,0 +1,2 @@
+CREATE INDEX IF NOT EXISTS idx_messages_process_id_timestamp ON messages (process_id, "timestamp");
+DROP INDEX IF EXISTS idx_messages_process_id_timestamp_row_id;
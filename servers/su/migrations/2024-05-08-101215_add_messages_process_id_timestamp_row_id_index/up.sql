@@ -0,0 +1,5 @@
+-- get_messages orders by (timestamp, row_id) within a process, with row_id
+-- in the index the page comes back sorted without a sort step. it replaces
+-- the (process_id, timestamp) index, which is a prefix of it
+CREATE INDEX IF NOT EXISTS idx_messages_process_id_timestamp_row_id ON messages (process_id, "timestamp", row_id);
+DROP INDEX IF EXISTS idx_messages_process_id_timestamp;
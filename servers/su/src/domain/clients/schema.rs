@@ -15,12 +15,16 @@ table! {
         process_id -> Varchar,
         message_id -> Varchar,
         assignment_id -> Nullable<Varchar>,
-        message_data -> Jsonb,
+        message_data -> Nullable<Jsonb>,
         epoch -> Int4,
         nonce -> Int4,
         timestamp -> BigInt,
-        bundle -> Bytea,
+        bundle -> Nullable<Bytea>,
         hash_chain -> Text,
+        inserted_at -> Timestamptz,
+        bundle_size -> Int4,
+        bundle_hash -> Nullable<Varchar>,
+        bundle_codec -> Int2,
     }
 }
 
@@ -40,4 +44,38 @@ table! {
     }
 }
 
-allow_tables_to_appear_in_same_query!(processes, messages, schedulers, process_schedulers,);
+table! {
+    message_tags (row_id) {
+        row_id -> Int4,
+        message_row_id -> Int4,
+        name -> Text,
+        value -> Text,
+    }
+}
+
+table! {
+    process_activity (process_id) {
+        process_id -> Varchar,
+        last_timestamp -> BigInt,
+    }
+}
+
+table! {
+    bundles (hash) {
+        hash -> Varchar,
+        bundle -> Bytea,
+        codec -> Int2,
+    }
+}
+
+joinable!(message_tags -> messages (message_row_id));
+
+allow_tables_to_appear_in_same_query!(
+    processes,
+    messages,
+    schedulers,
+    process_schedulers,
+    message_tags,
+    process_activity,
+    bundles,
+);
@@ -1,24 +1,94 @@
+use std::borrow::Cow;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::env::VarError;
+use std::hash::{Hash, Hasher};
+use std::io::{BufRead, BufReader, BufWriter, Cursor, ErrorKind, Read, Write};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use diesel::pg::PgConnection;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use futures::stream::{self, BoxStream, StreamExt};
+
+use diesel::migration::{Migration, MigrationSource};
+use diesel::pg::{Pg, PgConnection};
 use diesel::prelude::*;
 use diesel::r2d2::ConnectionManager;
+use diesel::r2d2::CustomizeConnection;
 use diesel::r2d2::Pool;
-use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
+use diesel::sql_types::{Array, BigInt, Bytea, Int4, Text};
+use sha2::{Digest, Sha256};
+use tokio::sync::{mpsc, oneshot};
+
+use serde::{Deserialize, Serialize};
+
+use diesel_migrations::{
+    embed_migrations, EmbeddedMigrations, FileBasedMigrations, MigrationHarness,
+};
 
 use super::super::core::dal::{
-    DataStore, JsonErrorType, Message, PaginatedMessages, Process, ProcessScheduler, Scheduler,
-    StoreErrorType,
+    BatchSaveReport, DataStore, DeleteReport, Entity, ExportedMessage, ExportedMessageKey,
+    JsonErrorType, Log, MatchedBy, Message, MessageRange, PaginatedMessages, Process,
+    ProcessScheduler, RenameReport, SaveHook, SaveOutcome, Scheduler, SortOrder, StoreErrorType,
+    TailedMessages,
 };
 use crate::domain::config::AoConfig;
 
 pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!("./migrations");
 
+/*
+    postgres advisory lock key held while migrations
+    or maintenance run, so the two never overlap even
+    across several su nodes sharing a database
+*/
+const SCHEMA_LOCK_KEY: i64 = 0x5355_4d41_494e;
+
+// the r2d2 default, set explicitly so DB_MIN_IDLE can be capped at it
+const POOL_MAX_SIZE: u32 = 10;
+
+// extra attempts a SERIALIZABLE_SAVES save gets after a serialization failure
+const SERIALIZATION_RETRIES: u32 = 3;
+
+/*
+    a multi row insert binds one parameter per column per
+    row and postgres allows 65535, NewMessage has 12 columns
+    and NewMessageTag 3
+*/
+const MAX_INSERT_CHUNK_SIZE: usize = 65535 / 12;
+const TAG_INSERT_CHUNK_SIZE: usize = 65535 / 3;
+
+// messages read per query by reindex_process and the bundle sweeps
+const SWEEP_BATCH_SIZE: i64 = 500;
+
+// bundle_codec values, how the stored bundle bytes are encoded
+const BUNDLE_CODEC_RAW: i16 = 0;
+const BUNDLE_CODEC_ZSTD: i16 = 1;
+
+// zstd's own default, most of the ratio for little cpu
+const ZSTD_LEVEL: i32 = 3;
+
+const MAINTENANCE_TABLES: [&str; 4] = ["messages", "processes", "process_schedulers", "schedulers"];
+
+diesel::sql_function!(fn pg_try_advisory_lock(key: BigInt) -> Bool);
+diesel::sql_function!(fn pg_advisory_unlock(key: BigInt) -> Bool);
+diesel::sql_function!(fn octet_length(x: Bytea) -> Int4);
+diesel::sql_function!(fn substring(x: Bytea, start: Int4, count: Int4) -> Bytea);
+
+use diesel::result::DatabaseErrorKind;
 use diesel::result::Error as DieselError; // Import Diesel's Error
 
 impl From<DieselError> for StoreErrorType {
     fn from(diesel_error: DieselError) -> Self {
-        StoreErrorType::DatabaseError(format!("{:?}", diesel_error))
+        match &diesel_error {
+            // postgres cancelled the query because it ran past STATEMENT_TIMEOUT_MS
+            DieselError::DatabaseError(_, info) if info.message().contains("statement timeout") => {
+                StoreErrorType::QueryTimeout(format!("{:?}", diesel_error))
+            }
+            _ => StoreErrorType::DatabaseError(format!("{:?}", diesel_error)),
+        }
     }
 }
 
@@ -34,9 +104,14 @@ impl From<JsonErrorType> for StoreErrorType {
     }
 }
 
+/*
+    Strings built from store errors end up in http
+    responses so they get the Display message, see
+    flows::store_error for logging the detail first
+*/
 impl From<StoreErrorType> for String {
     fn from(error: StoreErrorType) -> Self {
-        format!("{:?}", error)
+        error.to_string()
     }
 }
 
@@ -52,63 +127,421 @@ impl From<diesel::prelude::ConnectionError> for StoreErrorType {
     }
 }
 
+impl From<std::io::Error> for StoreErrorType {
+    fn from(error: std::io::Error) -> Self {
+        StoreErrorType::IoError(format!("data store io error: {}", error))
+    }
+}
+
 impl From<std::num::ParseIntError> for StoreErrorType {
     fn from(error: std::num::ParseIntError) -> Self {
         StoreErrorType::IntError(format!("data store int error: {}", error))
     }
 }
 
+#[derive(Debug)]
+pub struct PoolStats {
+    pub max_size: u32,
+    pub connections: u32,
+    pub idle_connections: u32,
+    pub acquires: u64,
+    pub avg_acquire_micros: u64,
+    pub slow_acquires: u64,
+}
+
+/*
+    framing of export_all. Ndjson is one json object per
+    line with the bundle base64url encoded in it.
+    LengthPrefixed skips the encoding, each message is a
+    u32 big endian length and a json header without the
+    bundle, then a u64 big endian length and the raw bundle.
+    import_all reads both back
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Ndjson,
+    LengthPrefixed,
+}
+
+#[derive(Debug, Default)]
+pub struct ExportStats {
+    pub messages: u64,
+    // bytes written, framing included
+    pub bytes: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct ImportStats {
+    pub inserted: u64,
+    // already stored at their process, epoch and nonce
+    pub skipped: u64,
+    // unreadable frames, bundles that don't match their ids, failed saves
+    pub failed: u64,
+}
+
+// one exported message, the bundle is only inlined in Ndjson
+#[derive(Serialize, Deserialize)]
+struct ExportFrame {
+    message_id: String,
+    assignment_id: Option<String>,
+    process_id: String,
+    timestamp: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    bundle: Option<String>,
+}
+
+// running totals for get_conn, shared by every clone of the client
+#[derive(Default)]
+struct AcquireStats {
+    acquires: AtomicU64,
+    wait_micros: AtomicU64,
+    slow: AtomicU64,
+}
+
+type IngestJob = (
+    Message,
+    Vec<u8>,
+    oneshot::Sender<Result<SaveOutcome, StoreErrorType>>,
+);
+
+/*
+    bounded queues in front of save_message, one per
+    worker. a process always hashes to the same worker
+    so its saves run one at a time in the order they
+    were queued, different processes spread over the
+    workers. a full queue makes the caller wait
+*/
+struct IngestQueue {
+    senders: Vec<mpsc::Sender<IngestJob>>,
+}
+
+impl IngestQueue {
+    fn start(store: StoreClient, workers: usize, depth: usize) -> IngestQueue {
+        let mut senders = Vec::with_capacity(workers);
+        for _ in 0..workers {
+            let (sender, mut receiver) = mpsc::channel::<IngestJob>(depth);
+            let store = store.clone();
+            tokio::spawn(async move {
+                while let Some((message, bundle_in, reply)) = receiver.recv().await {
+                    let result = store.save_message(&message, &bundle_in).await;
+                    // the caller may have stopped waiting, the save still stands
+                    let _ = reply.send(result);
+                }
+            });
+            senders.push(sender);
+        }
+        IngestQueue { senders }
+    }
+
+    fn sender_for(&self, process_id_in: &str) -> &mpsc::Sender<IngestJob> {
+        let mut hasher = DefaultHasher::new();
+        process_id_in.hash(&mut hasher);
+        &self.senders[(hasher.finish() % self.senders.len() as u64) as usize]
+    }
+}
+
+/*
+    applied to every connection as the pool opens it.
+    the statement timeout stops one runaway query from
+    holding a connection forever, the search_path points
+    every unqualified table at DB_SCHEMA
+*/
+#[derive(Debug)]
+struct ConnectionSettings {
+    statement_timeout_ms: u64,
+    db_schema: Option<String>,
+}
+
+impl CustomizeConnection<PgConnection, diesel::r2d2::Error> for ConnectionSettings {
+    fn on_acquire(&self, conn: &mut PgConnection) -> Result<(), diesel::r2d2::Error> {
+        if let Some(schema) = &self.db_schema {
+            diesel::sql_query(format!("SET search_path TO {}", quote_ident(schema)))
+                .execute(conn)
+                .map_err(diesel::r2d2::Error::QueryError)?;
+        }
+        if self.statement_timeout_ms > 0 {
+            diesel::sql_query(format!(
+                "SET statement_timeout = {}",
+                self.statement_timeout_ms
+            ))
+            .execute(conn)
+            .map_err(diesel::r2d2::Error::QueryError)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone)]
 pub struct StoreClient {
     pool: Pool<ConnectionManager<PgConnection>>,
+    enable_process_deletion: bool,
+    migrations_dir: Option<String>,
+    db_schema: Option<String>,
+    maintenance_vacuum: bool,
+    statement_timeout_ms: u64,
+    omit_message_data: bool,
+    serializable_saves: bool,
+    slow_acquire_ms: u64,
+    insert_chunk_size: usize,
+    bundle_table: bool,
+    compress_bundles: bool,
+    acquire_stats: Arc<AcquireStats>,
+    logger: Arc<dyn Log>,
+    save_hook: Option<SaveHook>,
+    ingest_queue: Option<Arc<IngestQueue>>,
 }
 
 impl StoreClient {
-    pub fn new() -> Result<Self, StoreErrorType> {
-        let config = AoConfig::new(Some("su".to_string())).expect("Failed to read configuration");
-        let database_url = config.database_url;
-        let manager = ConnectionManager::<PgConnection>::new(database_url);
-        let pool = Pool::builder()
-            .test_on_check_out(true)
-            .build(manager)
-            .map_err(|_| {
-                StoreErrorType::DatabaseError("Failed to initialize connection pool.".to_string())
-            })?;
+    // reads the configuration from the environment, a bad one is an EnvVarError
+    pub fn new(logger: Arc<dyn Log>) -> Result<Self, StoreErrorType> {
+        let config = AoConfig::new(Some("su".to_string())).map_err(StoreErrorType::EnvVarError)?;
+        StoreClient::from_config(&config, logger)
+    }
+
+    pub(crate) fn from_config(
+        config: &AoConfig,
+        logger: Arc<dyn Log>,
+    ) -> Result<Self, StoreErrorType> {
+        config
+            .validate()
+            .map_err(|e| StoreErrorType::EnvVarError(e.to_string()))?;
+        let manager = ConnectionManager::<PgConnection>::new(&config.database_url);
+        let mut builder = Pool::builder()
+            .max_size(POOL_MAX_SIZE)
+            .test_on_check_out(true);
+        /*
+            build() blocks until min_idle connections are
+            open, unset it defaults to the whole pool
+        */
+        if let Some(min_idle) = config.db_min_idle {
+            builder = builder.min_idle(Some(min_idle.min(POOL_MAX_SIZE)));
+        }
+        // 0 keeps the r2d2 defaults
+        if config.db_max_lifetime_secs > 0 {
+            builder = builder.max_lifetime(Some(Duration::from_secs(config.db_max_lifetime_secs)));
+        }
+        if config.db_idle_timeout_secs > 0 {
+            builder = builder.idle_timeout(Some(Duration::from_secs(config.db_idle_timeout_secs)));
+        }
+        if config.db_connection_timeout_secs > 0 {
+            builder =
+                builder.connection_timeout(Duration::from_secs(config.db_connection_timeout_secs));
+        }
+        if config.statement_timeout_ms > 0 || config.db_schema.is_some() {
+            builder = builder.connection_customizer(Box::new(ConnectionSettings {
+                statement_timeout_ms: config.statement_timeout_ms,
+                db_schema: config.db_schema.clone(),
+            }));
+        }
+        let pool = builder.build(manager).map_err(|_| {
+            StoreErrorType::DatabaseError("Failed to initialize connection pool.".to_string())
+        })?;
+
+        Ok(StoreClient {
+            pool,
+            enable_process_deletion: config.enable_process_deletion,
+            migrations_dir: config.migrations_dir.clone(),
+            db_schema: config.db_schema.clone(),
+            maintenance_vacuum: config.maintenance_vacuum,
+            statement_timeout_ms: config.statement_timeout_ms,
+            omit_message_data: config.omit_message_data,
+            serializable_saves: config.serializable_saves,
+            slow_acquire_ms: config.slow_acquire_ms,
+            insert_chunk_size: config.insert_chunk_size.clamp(1, MAX_INSERT_CHUNK_SIZE),
+            bundle_table: config.bundle_table,
+            compress_bundles: config.compress_bundles,
+            acquire_stats: Arc::new(AcquireStats::default()),
+            logger,
+            save_hook: None,
+            ingest_queue: None,
+        })
+    }
+
+    pub fn with_save_hook(mut self, hook: SaveHook) -> Self {
+        self.save_hook = Some(hook);
+        self
+    }
 
-        Ok(StoreClient { pool })
+    /*
+        starts the workers behind queued_save_message, call
+        it last so they get a copy of the finished client
+    */
+    pub fn with_ingest_queue(mut self, workers: usize, depth: usize) -> Self {
+        if workers > 0 {
+            let queue = IngestQueue::start(self.clone(), workers, depth.max(1));
+            self.ingest_queue = Some(Arc::new(queue));
+        }
+        self
+    }
+
+    /*
+        runs the save hook for a committed message, an
+        error or panic in it is logged and never reaches
+        the save
+    */
+    fn notify_saved(&self, message_id_in: &str, process_id_in: &str) {
+        let hook = match &self.save_hook {
+            Some(hook) => hook,
+            None => return,
+        };
+        match panic::catch_unwind(AssertUnwindSafe(|| hook(message_id_in, process_id_in))) {
+            Ok(Ok(())) => (),
+            Ok(Err(e)) => self
+                .logger
+                .error(format!("save hook failed for {}: {}", message_id_in, e)),
+            Err(_) => self
+                .logger
+                .error(format!("save hook panicked for {}", message_id_in)),
+        }
     }
 
+    /*
+        waiting on pool.get() is where a saturated pool
+        shows up, so every checkout is timed and the slow
+        ones logged
+    */
     pub fn get_conn(
         &self,
     ) -> Result<diesel::r2d2::PooledConnection<ConnectionManager<PgConnection>>, StoreErrorType>
     {
-        self.pool.get().map_err(|_| {
-            StoreErrorType::DatabaseError("Failed to get connection from pool.".to_string())
+        let start = Instant::now();
+        let result = self.pool.get();
+        let waited = start.elapsed();
+
+        self.acquire_stats.acquires.fetch_add(1, Ordering::Relaxed);
+        self.acquire_stats
+            .wait_micros
+            .fetch_add(waited.as_micros() as u64, Ordering::Relaxed);
+        if self.slow_acquire_ms > 0 && waited >= Duration::from_millis(self.slow_acquire_ms) {
+            self.acquire_stats.slow.fetch_add(1, Ordering::Relaxed);
+            self.logger.log(format!(
+                "slow db connection acquire - {}ms, pool connections: {}, idle: {}",
+                waited.as_millis(),
+                self.pool.state().connections,
+                self.pool.state().idle_connections
+            ));
+        }
+
+        // r2d2 only fails a checkout once the connection timeout runs out
+        result.map_err(|e| {
+            StoreErrorType::PoolTimeout(format!("Failed to get connection from pool: {}", e))
         })
     }
 
     /*
-        run at server startup to modify the database as needed
+        runs a one off query that isn't worth a DataStore
+        method on a pooled connection, inside a READ ONLY
+        transaction so it can't write by accident
     */
-    pub fn run_migrations(&self) -> Result<String, StoreErrorType> {
+    pub fn with_read_conn<F, R>(&self, f: F) -> Result<R, StoreErrorType>
+    where
+        F: FnOnce(&mut PgConnection) -> Result<R, DieselError>,
+    {
         let conn = &mut self.get_conn()?;
-        match conn.run_pending_migrations(MIGRATIONS) {
-            Ok(m) => Ok(format!("Migrations applied... {:?}", m)),
-            Err(e) => Err(StoreErrorType::DatabaseError(format!(
-                "Error applying migrations: {}",
-                e.to_string()
-            ))),
+        Ok(conn.build_transaction().read_only().run(f)?)
+    }
+
+    /*
+        snapshot of the connection pool, cheap enough
+        to call on every tick of a monitoring loop
+    */
+    pub fn pool_stats(&self) -> PoolStats {
+        let state = self.pool.state();
+        let acquires = self.acquire_stats.acquires.load(Ordering::Relaxed);
+        let wait_micros = self.acquire_stats.wait_micros.load(Ordering::Relaxed);
+        PoolStats {
+            max_size: self.pool.max_size(),
+            connections: state.connections,
+            idle_connections: state.idle_connections,
+            acquires,
+            avg_acquire_micros: wait_micros.checked_div(acquires).unwrap_or(0),
+            slow_acquires: self.acquire_stats.slow.load(Ordering::Relaxed),
         }
     }
-}
 
-impl DataStore for StoreClient {
-    fn save_process(&self, process: &Process, bundle_in: &[u8]) -> Result<String, StoreErrorType> {
+    /*
+        check out n connections at once so the pool opens
+        the ones it is missing before the first requests
+        arrive. the pool already starts with min_idle open
+        so this only does anything when DB_MIN_IDLE is
+        below the pool size, returns how many new
+        connections were established
+    */
+    pub fn warmup(&self, n: usize) -> usize {
+        let n = n.min(self.pool.max_size() as usize);
+        let before = self.pool.state().connections;
+        let mut conns = Vec::with_capacity(n);
+        for _ in 0..n {
+            // stop at the first failure rather than waiting out the timeout n times
+            match self.pool.get() {
+                Ok(conn) => conns.push(conn),
+                Err(_) => break,
+            }
+        }
+        self.pool.state().connections.saturating_sub(before) as usize
+    }
+
+    /*
+        run at server startup to modify the database as needed.
+        uses the migrations embedded in the binary unless
+        MIGRATIONS_DIR points at a directory to run instead,
+        which lets tests layer extra migrations on top
+    */
+    pub fn run_migrations(&self) -> Result<String, StoreErrorType> {
+        let conn = &mut self.get_conn()?;
+        // wait out any maintenance run rather than skipping migrations
+        diesel::sql_query("SELECT pg_advisory_lock($1)")
+            .bind::<BigInt, _>(SCHEMA_LOCK_KEY)
+            .execute(conn)?;
+        /*
+            nothing in here may return early, the lock is
+            held by the session so it would stay taken on
+            the pooled connection and block every later run
+        */
+        let result = (|| -> Result<String, StoreErrorType> {
+            self.set_statement_timeout(conn, 0)?;
+            // the connection's search_path already points here, it may just not exist yet
+            if let Some(schema) = &self.db_schema {
+                diesel::sql_query(format!(
+                    "CREATE SCHEMA IF NOT EXISTS {}",
+                    quote_ident(schema)
+                ))
+                .execute(conn)?;
+            }
+            let applied = match &self.migrations_dir {
+                Some(dir) => match FileBasedMigrations::from_path(dir) {
+                    Ok(migrations) => conn
+                        .run_pending_migrations(migrations)
+                        .map(|m| format!("{:?}", m)),
+                    Err(e) => Err(format!("reading migrations from {}: {}", dir, e).into()),
+                },
+                None => conn
+                    .run_pending_migrations(MIGRATIONS)
+                    .map(|m| format!("{:?}", m)),
+            }
+            .map_err(|e| {
+                StoreErrorType::DatabaseError(format!("Error applying migrations: {}", e))
+            });
+            self.set_statement_timeout(conn, self.statement_timeout_ms)?;
+            applied
+        })();
+        let unlocked = diesel::select(pg_advisory_unlock(SCHEMA_LOCK_KEY)).get_result::<bool>(conn);
+        let applied = result?;
+        unlocked?;
+        Ok(format!("Migrations applied... {}", applied))
+    }
+
+    fn save_process_blocking(
+        &self,
+        process: &Process,
+        bundle_in: &[u8],
+    ) -> Result<String, StoreErrorType> {
         use super::schema::processes::dsl::*;
         let conn = &mut self.get_conn()?;
 
         let new_process = NewProcess {
             process_id: &process.process_id,
-            process_data: serde_json::to_value(process).expect("Failed to serialize Process"),
+            process_data: serde_json::to_value(process)?,
             bundle: bundle_in,
         };
 
@@ -123,177 +556,1594 @@ impl DataStore for StoreClient {
         }
     }
 
-    fn get_process(&self, process_id_in: &str) -> Result<Process, StoreErrorType> {
-        use super::schema::processes::dsl::*;
+    fn save_message_blocking(
+        &self,
+        message: &Message,
+        bundle_in: &[u8],
+    ) -> Result<SaveOutcome, StoreErrorType> {
+        use super::schema::messages::dsl::*;
         let conn = &mut self.get_conn()?;
 
-        let db_process_result: Result<Option<DbProcess>, DieselError> = processes
-            .filter(process_id.eq(process_id_in))
-            .first(conn)
-            .optional();
+        self.check_existing_message_on(conn, message)?;
 
-        match db_process_result {
-            Ok(Some(db_process)) => {
-                let process: Process = serde_json::from_value(db_process.process_data.clone())?;
-                Ok(process)
+        let stored_hash = self.stored_bundle_hash(bundle_in);
+        let (stored_bytes, stored_codec) = self.encode_bundle(bundle_in);
+        let new_message = NewMessage {
+            process_id: &message.process_id()?,
+            message_id: &message.message_id()?,
+            assignment_id: message.stored_assignment_id(),
+            message_data: self.stored_message_data(message)?,
+            epoch: &message.epoch()?,
+            nonce: &message.nonce()?,
+            timestamp: &message.timestamp()?,
+            bundle: match stored_hash {
+                Some(_) => None,
+                None => Some(&stored_bytes),
+            },
+            hash_chain: &message.hash_chain()?,
+            bundle_size: bundle_in.len() as i32,
+            bundle_hash: stored_hash.clone(),
+            bundle_codec: match stored_hash {
+                Some(_) => BUNDLE_CODEC_RAW,
+                None => stored_codec,
+            },
+        };
+
+        // only the tags of the message itself are indexed, not the assignment's
+        let tags = match &message.message {
+            Some(m) => m.tags.as_slice(),
+            None => &[],
+        };
+
+        /*
+            a row already on this process, epoch and nonce is
+            only this save repeated if it holds the same ids,
+            anything else rolls back and fails the save below
+        */
+        let write = |conn: &mut PgConnection| -> Result<SaveOutcome, DieselError> {
+            if let Some(hash_in) = &stored_hash {
+                diesel::insert_into(super::schema::bundles::table)
+                    .values(&NewBundle {
+                        hash: hash_in,
+                        bundle: &stored_bytes,
+                        codec: stored_codec,
+                    })
+                    .on_conflict(super::schema::bundles::hash)
+                    .do_nothing()
+                    .execute(conn)?;
             }
-            Ok(None) => Err(StoreErrorType::NotFound("Process not found".to_string())),
-            Err(e) => Err(StoreErrorType::from(e)),
+
+            let inserted_row_id = diesel::insert_into(messages)
+                .values(&new_message)
+                .on_conflict((process_id, epoch, nonce))
+                .do_nothing()
+                .returning(row_id)
+                .get_result::<i32>(conn)
+                .optional()?;
+            let message_row_id = match inserted_row_id {
+                Some(message_row_id) => message_row_id,
+                None => {
+                    return match stored_on_key(conn, &new_message)? {
+                        Some((existing_row_id, true)) => Ok(SaveOutcome::AlreadyExists {
+                            row_id: existing_row_id,
+                        }),
+                        _ => Err(DieselError::RollbackTransaction),
+                    };
+                }
+            };
+
+            let new_tags: Vec<NewMessageTag> = tags
+                .iter()
+                .map(|tag| NewMessageTag {
+                    message_row_id,
+                    name: &tag.name,
+                    value: &tag.value,
+                })
+                .collect();
+            if !new_tags.is_empty() {
+                diesel::insert_into(super::schema::message_tags::table)
+                    .values(&new_tags)
+                    .execute(conn)?;
+            }
+
+            touch_process_activity(conn, new_message.process_id, *new_message.timestamp)?;
+            Ok(SaveOutcome::Inserted {
+                row_id: message_row_id,
+            })
+        };
+        let nonce_taken = || {
+            StoreErrorType::MessageExists(format!(
+                "Nonce {} of process {} is already taken by another message",
+                new_message.nonce, new_message.process_id
+            ))
+        };
+
+        if !self.serializable_saves {
+            return match conn.transaction(|conn| write(conn)) {
+                Ok(outcome) => Ok(outcome),
+                Err(DieselError::RollbackTransaction) => Err(nonce_taken()),
+                Err(e) => Err(StoreErrorType::from(e)),
+            };
         }
-    }
 
-    /*
-        If we are trying to write an actual data item
-        not just an assignment we need to check that it
-        doesnt already exist.
-    */
-    fn check_existing_message(&self, message: &Message) -> Result<(), StoreErrorType> {
-        match &message.message {
-            Some(m) => {
-                match self.get_message(&m.id) {
-                    Ok(parsed) => {
-                        /*
-                            If the message already exists and it contains
-                            an actual message (it is not just an assignment)
-                            then throw an error to avoid duplicate data items
-                            being written
-                        */
-                        match parsed.message {
-                            Some(_) => Err(StoreErrorType::MessageExists(
-                                "Message already exists".to_string(),
-                            )),
-                            None => Ok(()),
+        /*
+            the latest nonce is read inside the transaction so
+            two concurrent saves to one process conflict and
+            postgres aborts one of them, the retry then sees
+            the other's row. a save repeated after its row is
+            stored gets the same answer as it would without
+            SERIALIZABLE_SAVES rather than a nonce error
+        */
+        let mut attempt = 0;
+        loop {
+            let result = conn
+                .build_transaction()
+                .serializable()
+                .run::<Result<SaveOutcome, i32>, DieselError, _>(|conn| {
+                    let latest_nonce = messages
+                        .filter(process_id.eq(new_message.process_id))
+                        .order(row_id.desc())
+                        .select(nonce)
+                        .first::<i32>(conn)
+                        .optional()?;
+                    match latest_nonce {
+                        Some(latest) if latest >= *new_message.nonce => {
+                            match stored_on_key(conn, &new_message)? {
+                                Some((existing_row_id, true)) => {
+                                    Ok(Ok(SaveOutcome::AlreadyExists {
+                                        row_id: existing_row_id,
+                                    }))
+                                }
+                                Some((_, false)) => Err(DieselError::RollbackTransaction),
+                                None => Ok(Err(latest)),
+                            }
                         }
+                        _ => write(conn).map(Ok),
                     }
-                    // The message wasnt found at all so it can be written
-                    Err(StoreErrorType::NotFound(_)) => Ok(()),
-                    // Some other error happened
-                    Err(_) => Err(StoreErrorType::DatabaseError(
-                        "Error checking message".to_string(),
-                    )),
+                });
+
+            match result {
+                Ok(Ok(outcome)) => return Ok(outcome),
+                Ok(Err(latest)) => {
+                    return Err(StoreErrorType::NotAllowed(format!(
+                        "Message nonce {} is not after the latest nonce {} for process {}",
+                        new_message.nonce, latest, new_message.process_id
+                    )))
                 }
+                Err(DieselError::DatabaseError(DatabaseErrorKind::SerializationFailure, _))
+                    if attempt < SERIALIZATION_RETRIES =>
+                {
+                    attempt += 1;
+                }
+                Err(DieselError::RollbackTransaction) => return Err(nonce_taken()),
+                Err(e) => return Err(StoreErrorType::from(e)),
             }
-            None => Ok(()),
         }
     }
 
-    fn save_message(&self, message: &Message, bundle_in: &[u8]) -> Result<String, StoreErrorType> {
-        use super::schema::messages::dsl::*;
-        let conn = &mut self.get_conn()?;
+    // the bytes to store for a bundle and their codec, raw unless COMPRESS_BUNDLES is set
+    fn encode_bundle<'a>(&self, bundle_in: &'a [u8]) -> (Cow<'a, [u8]>, i16) {
+        let compressed = match self.compress_bundles {
+            true => compress_bundle(bundle_in),
+            false => None,
+        };
+        match compressed {
+            Some(compressed) => (Cow::Owned(compressed), BUNDLE_CODEC_ZSTD),
+            None => (Cow::Borrowed(bundle_in), BUNDLE_CODEC_RAW),
+        }
+    }
 
-        self.check_existing_message(message)?;
+    // None unless BUNDLE_TABLE is set
+    fn stored_bundle_hash(&self, bundle_in: &[u8]) -> Option<String> {
+        match self.bundle_table {
+            true => Some(bundle_hash_of(bundle_in)),
+            false => None,
+        }
+    }
 
-        let new_message = NewMessage {
-            process_id: &message.process_id()?,
-            message_id: &message.message_id()?,
-            assignment_id: &message.assignment_id()?,
-            message_data: serde_json::to_value(message).expect("Failed to serialize Message"),
-            epoch: &message.epoch()?,
-            nonce: &message.nonce()?,
-            timestamp: &message.timestamp()?,
-            bundle: bundle_in,
-            hash_chain: &message.hash_chain()?,
-        };
+    fn stored_message_data(
+        &self,
+        message: &Message,
+    ) -> Result<Option<serde_json::Value>, StoreErrorType> {
+        match self.omit_message_data {
+            true => Ok(None),
+            false => Ok(Some(serde_json::to_value(message)?)),
+        }
+    }
 
-        match diesel::insert_into(messages)
-            .values(&new_message)
-            .execute(conn)
-        {
-            Ok(row_count) => {
-                if row_count == 0 {
-                    Err(StoreErrorType::DatabaseError(
-                        "Error saving message".to_string(),
-                    )) // Return a custom error for duplicates
-                } else {
-                    Ok("saved".to_string())
+    /*
+        the batch is written in chunks of INSERT_CHUNK_SIZE,
+        each in its own transaction, so one insert stays under
+        postgres's bind parameter limit and the table isn't
+        held for the whole ingest. chunks committed before a
+        failure stay committed and are counted in the report,
+        which comes with the batch indexes that were inserted
+    */
+    fn save_messages_batch_indexed(
+        &self,
+        batch: &[(Message, Vec<u8>)],
+    ) -> (BatchSaveReport, Vec<usize>) {
+        let mut report = BatchSaveReport {
+            saved: 0,
+            skipped: 0,
+            error: None,
+        };
+        let mut inserted = vec![];
+        let mut chunk_start = 0;
+        for chunk in batch.chunks(self.insert_chunk_size) {
+            match self.save_messages_chunk(chunk) {
+                Ok(chunk_inserted) => {
+                    report.saved += chunk_inserted.len();
+                    report.skipped += chunk.len() - chunk_inserted.len();
+                    inserted.extend(chunk_inserted.iter().map(|index| chunk_start + index));
+                }
+                Err(e) => {
+                    report.error = Some(e);
+                    break;
                 }
             }
-            Err(e) => Err(StoreErrorType::from(e)),
+            chunk_start += chunk.len();
         }
+        (report, inserted)
     }
 
-    fn get_messages(
+    /*
+        rows that collide with a stored one on process, epoch
+        and nonce are skipped by the insert itself, any other
+        unique violation fails the chunk. a data item that is
+        already stored is skipped too, as check_existing_message
+        does for single saves. returns the chunk indexes that
+        were inserted
+    */
+    fn save_messages_chunk(
         &self,
-        process_id_in: &str,
-        from: &Option<String>,
-        to: &Option<String>,
-        limit: &Option<i32>,
-    ) -> Result<PaginatedMessages, StoreErrorType> {
+        chunk: &[(Message, Vec<u8>)],
+    ) -> Result<Vec<usize>, StoreErrorType> {
         use super::schema::messages::dsl::*;
-        let conn = &mut self.get_conn()?;
-        let mut query = messages.filter(process_id.eq(process_id_in)).into_boxed();
 
-        // Apply 'from' timestamp filtering if 'from' is provided
-        if let Some(from_timestamp_str) = from {
-            let from_timestamp = from_timestamp_str
-                .parse::<i64>()
-                .map_err(StoreErrorType::from)?;
-            query = query.filter(timestamp.gt(from_timestamp));
+        // owned column values for the NewMessages to borrow
+        let mut columns: Vec<(String, String, i32, i32, i64, String)> = vec![];
+        for (message, _) in chunk.iter() {
+            columns.push((
+                message.process_id()?,
+                message.message_id()?,
+                message.epoch()?,
+                message.nonce()?,
+                message.timestamp()?,
+                message.hash_chain()?,
+            ));
         }
 
-        // Apply 'to' timestamp filtering if 'to' is provided
-        if let Some(to_timestamp_str) = to {
-            let to_timestamp = to_timestamp_str
-                .parse::<i64>()
-                .map_err(StoreErrorType::from)?;
-            query = query.filter(timestamp.le(to_timestamp));
+        let stored_hashes: Vec<Option<String>> = chunk
+            .iter()
+            .map(|(_, bundle_in)| self.stored_bundle_hash(bundle_in))
+            .collect();
+        let encoded: Vec<(Cow<[u8]>, i16)> = chunk
+            .iter()
+            .map(|(_, bundle_in)| self.encode_bundle(bundle_in))
+            .collect();
+
+        let conn = &mut self.get_conn()?;
+        let mut stored_items = self.stored_data_item_ids(conn, chunk)?;
+
+        // the chunk index of each NewMessage
+        let mut new_indexes: Vec<usize> = vec![];
+        let mut new_messages: Vec<NewMessage> = vec![];
+        let mut new_bundles: Vec<NewBundle> = vec![];
+        let mut latest_timestamps: HashMap<&str, i64> = HashMap::new();
+        for (
+            chunk_index,
+            ((((message, bundle_in), column), stored_hash), (stored_bytes, stored_codec)),
+        ) in chunk
+            .iter()
+            .zip(columns.iter())
+            .zip(stored_hashes.iter())
+            .zip(encoded.iter())
+            .enumerate()
+        {
+            // a data item repeated within the chunk only goes in once as well
+            if let Some(item) = &message.message {
+                if !stored_items.insert(item.id.clone()) {
+                    continue;
+                }
+            }
+            new_indexes.push(chunk_index);
+            let (pid, mid, epoch_in, nonce_in, timestamp_in, hash_chain_in) = column;
+            if let Some(hash_in) = stored_hash {
+                new_bundles.push(NewBundle {
+                    hash: hash_in,
+                    bundle: stored_bytes,
+                    codec: *stored_codec,
+                });
+            }
+            new_messages.push(NewMessage {
+                process_id: pid,
+                message_id: mid,
+                assignment_id: message.stored_assignment_id(),
+                message_data: self.stored_message_data(message)?,
+                epoch: epoch_in,
+                nonce: nonce_in,
+                timestamp: timestamp_in,
+                bundle: match stored_hash {
+                    Some(_) => None,
+                    None => Some(stored_bytes),
+                },
+                hash_chain: hash_chain_in,
+                bundle_size: bundle_in.len() as i32,
+                bundle_hash: stored_hash.clone(),
+                bundle_codec: match stored_hash {
+                    Some(_) => BUNDLE_CODEC_RAW,
+                    None => *stored_codec,
+                },
+            });
+            let latest = latest_timestamps.entry(pid).or_insert(*timestamp_in);
+            *latest = (*latest).max(*timestamp_in);
         }
 
-        // Apply limit, converting Option<i32> to i64 and adding 1 to check for the next page
-        let limit_val = limit.unwrap_or(5000) as i64; // Default limit if none is provided
-        let db_messages_result: Result<Vec<DbMessage>, DieselError> = query
-            .order(timestamp.asc())
-            .limit(limit_val + 1) // Fetch one extra record to determine if a next page exists
-            .load(conn);
+        let inserted = conn.transaction::<Vec<usize>, DieselError, _>(|conn| {
+            if !new_bundles.is_empty() {
+                diesel::insert_into(super::schema::bundles::table)
+                    .values(&new_bundles)
+                    .on_conflict(super::schema::bundles::hash)
+                    .do_nothing()
+                    .execute(conn)?;
+            }
 
-        match db_messages_result {
-            Ok(db_messages) => {
-                let has_next_page = db_messages.len() as i64 > limit_val;
-                // Take only up to the limit if there's an extra indicating a next page
-                let messages_o = if has_next_page {
-                    &db_messages[..(limit_val as usize)]
-                } else {
-                    &db_messages[..]
-                };
+            let returned: Vec<(i32, String, i32, i32, Option<String>)> =
+                diesel::insert_into(messages)
+                    .values(&new_messages)
+                    .on_conflict((process_id, epoch, nonce))
+                    .do_nothing()
+                    .returning((row_id, process_id, epoch, nonce, assignment_id))
+                    .get_results(conn)?;
 
-                let mut messages_mapped: Vec<Message> = vec![];
-                for db_message in messages_o.iter() {
-                    let json = serde_json::from_value(db_message.message_data.clone())?;
-                    let bytes: Vec<u8> = db_message.bundle.clone();
-                    let mapped = Message::from_val(&json, bytes)?;
-                    messages_mapped.push(mapped);
+            // match the inserted rows back to the chunk, each row claimed once
+            let mut returned: HashMap<(String, i32, i32), (i32, Option<String>)> = returned
+                .into_iter()
+                .map(|(r, p, e, n, a)| ((p, e, n), (r, a)))
+                .collect();
+            let mut inserted: Vec<(usize, i32)> = vec![];
+            for (index, new_message) in new_messages.iter().enumerate() {
+                let key = (
+                    new_message.process_id.to_string(),
+                    *new_message.epoch,
+                    *new_message.nonce,
+                );
+                if let Some((_, stored_assignment_id)) = returned.get(&key) {
+                    if *stored_assignment_id == new_message.assignment_id {
+                        if let Some((message_row_id, _)) = returned.remove(&key) {
+                            inserted.push((new_indexes[index], message_row_id));
+                        }
+                    }
                 }
+            }
 
-                let paginated = PaginatedMessages::from_messages(messages_mapped, has_next_page)?;
-                Ok(paginated)
+            let new_tags: Vec<NewMessageTag> = inserted
+                .iter()
+                .flat_map(|(index, message_row_id)| {
+                    let tags = match &chunk[*index].0.message {
+                        Some(m) => m.tags.as_slice(),
+                        None => &[],
+                    };
+                    tags.iter().map(|tag| NewMessageTag {
+                        message_row_id: *message_row_id,
+                        name: &tag.name,
+                        value: &tag.value,
+                    })
+                })
+                .collect();
+            for tags_chunk in new_tags.chunks(TAG_INSERT_CHUNK_SIZE) {
+                diesel::insert_into(super::schema::message_tags::table)
+                    .values(tags_chunk)
+                    .execute(conn)?;
             }
-            Err(e) => Err(StoreErrorType::from(e)),
-        }
+
+            for (pid, latest) in latest_timestamps.iter() {
+                touch_process_activity(conn, pid, *latest)?;
+            }
+            Ok(inserted.into_iter().map(|(index, _)| index).collect())
+        })?;
+
+        Ok(inserted)
     }
 
-    fn get_message(&self, tx_id: &str) -> Result<Message, StoreErrorType> {
+    /*
+        the ids of the data items in chunk that are already
+        stored as data items. like check_existing_message a
+        message is looked up as get_message would find it,
+        by its oldest row, so a later assignment of it alone
+        doesn't count. one query for the whole chunk
+    */
+    fn stored_data_item_ids(
+        &self,
+        conn: &mut PgConnection,
+        chunk: &[(Message, Vec<u8>)],
+    ) -> Result<HashSet<String>, StoreErrorType> {
         use super::schema::messages::dsl::*;
-        let conn = &mut self.get_conn()?;
 
-        /*
-            get the oldest match. in the case of a message that has
-            later assignments, it should be the original message itself.
-        */
-        let db_message_result: Result<Option<DbMessage>, DieselError> = messages
-            .filter(message_id.eq(tx_id).or(assignment_id.eq(tx_id)))
-            .order(timestamp.asc())
-            .first(conn)
-            .optional();
+        let item_ids: Vec<&str> = chunk
+            .iter()
+            .filter_map(|(message, _)| message.message.as_ref().map(|m| m.id.as_str()))
+            .collect();
+        if item_ids.is_empty() {
+            return Ok(HashSet::new());
+        }
 
-        match db_message_result {
-            Ok(Some(db_message)) => {
-                let message_val: serde_json::Value =
-                    serde_json::from_value(db_message.message_data.clone())?;
-                let message: Message = Message::from_val(&message_val, db_message.bundle.clone())?;
-                Ok(message)
+        let db_messages: Vec<DbMessage> = messages
+            .filter(
+                message_id
+                    .eq_any(&item_ids)
+                    .or(assignment_id.eq_any(&item_ids)),
+            )
+            .order((timestamp.asc(), row_id.asc()))
+            .load(conn)?;
+        if db_messages.is_empty() {
+            return Ok(HashSet::new());
+        }
+
+        // the oldest row matching each id
+        let mut oldest: HashMap<&str, usize> = HashMap::new();
+        for (index, db_message) in db_messages.iter().enumerate() {
+            let matched = [
+                Some(db_message.message_id.as_str()),
+                db_message.assignment_id.as_deref(),
+            ];
+            for id in matched.into_iter().flatten() {
+                if item_ids.contains(&id) {
+                    oldest.entry(id).or_insert(index);
+                }
             }
-            Ok(None) => Err(StoreErrorType::NotFound("Message not found".to_string())), // Adjust this error type as needed
-            Err(e) => Err(StoreErrorType::from(e)),
+        }
+
+        let mapped = self.db_messages_to_messages(conn, &db_messages)?;
+        Ok(oldest
+            .into_iter()
+            .filter(|(_, index)| mapped[*index].message.is_some())
+            .map(|(id, _)| id.to_string())
+            .collect())
+    }
+
+    /*
+        migrations and maintenance are expected to run long,
+        they lift STATEMENT_TIMEOUT_MS on their connection
+        and restore it before it goes back to the pool
+    */
+    fn set_statement_timeout(
+        &self,
+        conn: &mut PgConnection,
+        millis: u64,
+    ) -> Result<(), StoreErrorType> {
+        if self.statement_timeout_ms > 0 {
+            diesel::sql_query(format!("SET statement_timeout = {}", millis)).execute(conn)?;
+        }
+        Ok(())
+    }
+
+    /*
+        refresh planner statistics on the main tables, and
+        VACUUM them too when MAINTENANCE_VACUUM is set. refuses
+        to start while migrations or another maintenance run
+        hold the schema lock instead of queueing behind them
+    */
+    pub fn run_maintenance(&self) -> Result<String, StoreErrorType> {
+        let conn = &mut self.get_conn()?;
+        let locked =
+            diesel::select(pg_try_advisory_lock(SCHEMA_LOCK_KEY)).get_result::<bool>(conn)?;
+        if !locked {
+            return Err(StoreErrorType::DatabaseError(
+                "Maintenance skipped, migrations or maintenance already running".to_string(),
+            ));
+        }
+
+        let start = Instant::now();
+        let command = match self.maintenance_vacuum {
+            true => "VACUUM (ANALYZE)",
+            false => "ANALYZE",
+        };
+        // as in run_migrations the lock must be released whatever happens in here
+        let result = (|| -> Result<(), StoreErrorType> {
+            self.set_statement_timeout(conn, 0)?;
+            let mut analyzed = Ok(0);
+            for table in MAINTENANCE_TABLES {
+                analyzed = diesel::sql_query(format!("{} {}", command, table)).execute(conn);
+                if analyzed.is_err() {
+                    break;
+                }
+            }
+            self.set_statement_timeout(conn, self.statement_timeout_ms)?;
+            analyzed?;
+            Ok(())
+        })();
+        let unlocked = diesel::select(pg_advisory_unlock(SCHEMA_LOCK_KEY)).get_result::<bool>(conn);
+        result?;
+        unlocked?;
+
+        Ok(format!(
+            "{} of {} tables finished in {}ms",
+            command,
+            MAINTENANCE_TABLES.len(),
+            start.elapsed().as_millis()
+        ))
+    }
+
+    /*
+        stream every message on the node to writer in row_id
+        order, for backups. messages are read SWEEP_BATCH_SIZE
+        at a time so memory stays flat however big the node is
+    */
+    pub fn export_all(
+        &self,
+        writer: impl Write,
+        format: ExportFormat,
+    ) -> Result<ExportStats, StoreErrorType> {
+        let mut writer = BufWriter::new(writer);
+        let mut stats = ExportStats::default();
+        let mut after_row_id = 0;
+        loop {
+            let (page, last_row_id) =
+                self.get_messages_for_export(after_row_id, SWEEP_BATCH_SIZE)?;
+            let last_row_id = match last_row_id {
+                Some(last_row_id) => last_row_id,
+                None => break,
+            };
+            for exported in page {
+                stats.bytes += write_export_frame(&mut writer, format, exported)?;
+                stats.messages += 1;
+            }
+            after_row_id = last_row_id;
+        }
+        writer.flush()?;
+        Ok(stats)
+    }
+
+    /*
+        restore a backup written by export_all. every bundle
+        is rebuilt and checked against the ids in its frame
+        before anything is written. messages go through the
+        batch save INSERT_CHUNK_SIZE at a time, the ones it
+        doesn't insert are looked up once per chunk so those
+        already stored count as skipped and a restore can be
+        rerun. a broken stream fails the import, the chunks
+        saved before it stay
+    */
+    pub fn import_all(
+        &self,
+        reader: impl Read,
+        format: ExportFormat,
+    ) -> Result<ImportStats, StoreErrorType> {
+        let mut reader = BufReader::new(reader);
+        let mut stats = ImportStats::default();
+        let mut pending: Vec<(Message, Vec<u8>)> = vec![];
+        while let Some(read) = read_export_frame(&mut reader, format)? {
+            let checked = read.and_then(|(frame, bundle_in)| {
+                let rebuilt = Message::from_bundle_bytes(bundle_in.clone())
+                    .map_err(|e| format!("bundle could not be parsed: {:?}", e))?;
+                match frame_matches_message(&frame, &rebuilt) {
+                    true => Ok((frame, rebuilt, bundle_in)),
+                    false => Err(format!(
+                        "bundle of message {} does not match its ids",
+                        frame.message_id
+                    )),
+                }
+            });
+            match checked {
+                Ok((_, message, bundle_in)) => pending.push((message, bundle_in)),
+                Err(e) => {
+                    self.logger.error(format!("import skipped a frame: {}", e));
+                    stats.failed += 1;
+                    continue;
+                }
+            }
+
+            if pending.len() >= self.insert_chunk_size {
+                self.import_pending(&mut pending, &mut stats);
+            }
+        }
+        self.import_pending(&mut pending, &mut stats);
+        Ok(stats)
+    }
+
+    /*
+        for each message, true when this exact message is
+        already stored at its process, epoch and nonce. false
+        means another message holds that nonce, or the data
+        item is stored elsewhere
+    */
+    fn stored_at_same_key(&self, batch: &[&Message]) -> Result<Vec<bool>, StoreErrorType> {
+        use super::schema::messages::dsl::*;
+        if batch.is_empty() {
+            return Ok(vec![]);
+        }
+        let conn = &mut self.get_conn()?;
+
+        let mut keys: Vec<(String, i32, i32)> = vec![];
+        for message in batch {
+            keys.push((message.process_id()?, message.epoch()?, message.nonce()?));
+        }
+        let process_ids: Vec<&str> = keys.iter().map(|k| k.0.as_str()).collect();
+        let nonces: Vec<i32> = keys.iter().map(|k| k.2).collect();
+
+        // process and nonce narrow it to about the batch, the exact keys are matched below
+        let stored: HashMap<(String, i32, i32), (String, Option<String>)> = messages
+            .filter(process_id.eq_any(&process_ids))
+            .filter(nonce.eq_any(&nonces))
+            .select((process_id, epoch, nonce, message_id, assignment_id))
+            .load::<(String, i32, i32, String, Option<String>)>(conn)?
+            .into_iter()
+            .map(|(p, e, n, m, a)| ((p, e, n), (m, a)))
+            .collect();
+
+        let mut same = vec![];
+        for (message, key) in batch.iter().zip(keys.iter()) {
+            same.push(match stored.get(key) {
+                Some((stored_message_id, stored_assignment_id)) => {
+                    *stored_message_id == message.message_id()?
+                        && *stored_assignment_id == message.stored_assignment_id()
+                }
+                None => false,
+            });
+        }
+        Ok(same)
+    }
+
+    fn import_pending(&self, pending: &mut Vec<(Message, Vec<u8>)>, stats: &mut ImportStats) {
+        if pending.is_empty() {
+            return;
+        }
+        let (report, inserted) = self.save_messages_batch_indexed(pending);
+        stats.inserted += report.saved as u64;
+
+        // chunks are written in order, these are the ones that were
+        let written = report.saved + report.skipped;
+        let inserted: HashSet<usize> = inserted.into_iter().collect();
+        let collided: Vec<&Message> = pending[..written]
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| !inserted.contains(index))
+            .map(|(_, (message, _))| message)
+            .collect();
+        match self.stored_at_same_key(&collided) {
+            Ok(same) => {
+                for (message, same) in collided.iter().zip(same) {
+                    if same {
+                        stats.skipped += 1;
+                        continue;
+                    }
+                    self.logger.error(format!(
+                        "import skipped message {}, its nonce or its data item is already stored",
+                        message.message_id().unwrap_or_default()
+                    ));
+                    stats.failed += 1;
+                }
+            }
+            Err(e) => {
+                self.logger.error(format!(
+                    "import could not check {} messages that were not saved: {:?}",
+                    collided.len(),
+                    e
+                ));
+                stats.failed += collided.len() as u64;
+            }
+        }
+
+        if let Some(e) = report.error {
+            let unsaved = pending.len() - written;
+            self.logger.error(format!(
+                "import failed to save {} messages: {:?}",
+                unsaved, e
+            ));
+            stats.failed += unsaved as u64;
+        }
+        pending.clear();
+    }
+
+    /*
+        If we are trying to write an actual data item
+        not just an assignment we need to check that it
+        doesnt already exist. it runs on the caller's
+        connection so a save never holds two from the pool
+    */
+    fn check_existing_message_on(
+        &self,
+        conn: &mut PgConnection,
+        message: &Message,
+    ) -> Result<(), StoreErrorType> {
+        match &message.message {
+            Some(m) => {
+                match self.message_matched_on(conn, &m.id) {
+                    Ok((parsed, _)) => {
+                        /*
+                            If the message already exists and it contains
+                            an actual message (it is not just an assignment)
+                            then throw an error to avoid duplicate data items
+                            being written
+                        */
+                        match parsed.message {
+                            Some(_) => Err(StoreErrorType::MessageExists(
+                                "Message already exists".to_string(),
+                            )),
+                            None => Ok(()),
+                        }
+                    }
+                    // The message wasnt found at all so it can be written
+                    Err(StoreErrorType::NotFound(Entity::Message, _)) => Ok(()),
+                    // Some other error happened, keep it so a db outage isnt hidden
+                    Err(e) => Err(with_context(e, "Error checking message")),
+                }
+            }
+            None => Ok(()),
+        }
+    }
+
+    // get_message_matched on a connection the caller already holds
+    fn message_matched_on(
+        &self,
+        conn: &mut PgConnection,
+        tx_id: &str,
+    ) -> Result<(Message, MatchedBy), StoreErrorType> {
+        use super::schema::messages::dsl::*;
+
+        /*
+            get the oldest match. in the case of a message that has
+            later assignments, it should be the original message itself.
+        */
+        let db_message_result: Result<Option<DbMessage>, DieselError> = messages
+            .filter(message_id.eq(tx_id).or(assignment_id.eq(tx_id)))
+            .order((timestamp.asc(), row_id.asc()))
+            .first(conn)
+            .optional();
+
+        match db_message_result {
+            Ok(Some(db_message)) => {
+                let message = self.db_message_to_message(conn, &db_message)?;
+                let matched_by = match db_message.message_id == tx_id {
+                    true => MatchedBy::Message,
+                    false => MatchedBy::Assignment,
+                };
+                Ok((message, matched_by))
+            }
+            Ok(None) => Err(StoreErrorType::NotFound(Entity::Message, tx_id.to_string())),
+            Err(e) => Err(StoreErrorType::from(e)),
+        }
+    }
+
+    fn db_message_to_message(
+        &self,
+        conn: &mut PgConnection,
+        db_message: &DbMessage,
+    ) -> Result<Message, StoreErrorType> {
+        let mut mapped = self.db_messages_to_messages(conn, std::slice::from_ref(db_message))?;
+        Ok(mapped.remove(0))
+    }
+
+    /*
+        message_data is NULL for rows saved with
+        OMIT_MESSAGE_DATA set, those are rebuilt
+        from the stored bundle instead. so is a row
+        whose message_data can't be read, as long as
+        its bundle still parses
+    */
+    fn db_messages_to_messages(
+        &self,
+        conn: &mut PgConnection,
+        db_messages: &[DbMessage],
+    ) -> Result<Vec<Message>, StoreErrorType> {
+        let stored_bundles = resolve_bundles(
+            conn,
+            db_messages
+                .iter()
+                .map(|m| (m.bundle.clone(), m.bundle_hash.clone(), m.bundle_codec))
+                .collect(),
+        )?;
+
+        let mut messages_mapped: Vec<Message> = vec![];
+        for (db_message, stored_bundle) in db_messages.iter().zip(stored_bundles) {
+            let message = match &db_message.message_data {
+                Some(message_val) => match Message::from_val(message_val, &stored_bundle) {
+                    Ok(message) => message,
+                    Err(e) => match Message::from_bundle_bytes(stored_bundle) {
+                        Ok(message) => {
+                            self.logger.error(format!(
+                                "message_data of message row {} unreadable, rebuilt from bundle: {:?}",
+                                db_message.row_id,
+                                e
+                            ));
+                            message
+                        }
+                        Err(_) => return Err(StoreErrorType::from(e)),
+                    },
+                },
+                None => Message::from_bundle_bytes(stored_bundle)?,
+            };
+            messages_mapped.push(message);
+        }
+        Ok(messages_mapped)
+    }
+}
+
+#[async_trait]
+impl DataStore for StoreClient {
+    /*
+        the inserts run on tokio's blocking pool so a slow
+        write doesn't stall the worker serving requests
+    */
+    async fn save_process(
+        &self,
+        process: &Process,
+        bundle_in: &[u8],
+    ) -> Result<String, StoreErrorType> {
+        let store = self.clone();
+        let process = process.clone();
+        let bundle_in = bundle_in.to_vec();
+        run_blocking(move || store.save_process_blocking(&process, &bundle_in)).await
+    }
+
+    fn get_process(&self, process_id_in: &str) -> Result<Process, StoreErrorType> {
+        use super::schema::processes::dsl::*;
+        let conn = &mut self.get_conn()?;
+
+        let db_process_result: Result<Option<DbProcess>, DieselError> = processes
+            .filter(process_id.eq(process_id_in))
+            .first(conn)
+            .optional();
+
+        match db_process_result {
+            Ok(Some(db_process)) => {
+                let process: Process = serde_json::from_value(db_process.process_data.clone())?;
+                Ok(process)
+            }
+            Ok(None) => Err(StoreErrorType::NotFound(
+                Entity::Process,
+                process_id_in.to_string(),
+            )),
+            Err(e) => Err(StoreErrorType::from(e)),
+        }
+    }
+
+    /*
+        the processes found among process_ids in one query,
+        missing ids are left out rather than failing
+    */
+    fn get_processes(&self, process_ids: &[String]) -> Result<Vec<Process>, StoreErrorType> {
+        use super::schema::processes::dsl::*;
+        if process_ids.is_empty() {
+            return Ok(vec![]);
+        }
+        let conn = &mut self.get_conn()?;
+
+        let found: Vec<serde_json::Value> = processes
+            .filter(process_id.eq_any(process_ids))
+            .order(row_id.asc())
+            .select(process_data)
+            .load(conn)?;
+
+        let mut processes_out = Vec::with_capacity(found.len());
+        for data in found {
+            processes_out.push(serde_json::from_value(data)?);
+        }
+        Ok(processes_out)
+    }
+
+    /*
+        the process and its latest message read in one
+        REPEATABLE READ transaction, so a message written
+        between the two reads can't end up in the pair
+    */
+    fn get_process_checkpoint(
+        &self,
+        process_id_in: &str,
+    ) -> Result<(Process, Option<Message>), StoreErrorType> {
+        use super::schema::messages::dsl as messages_dsl;
+        use super::schema::processes::dsl as processes_dsl;
+        let conn = &mut self.get_conn()?;
+
+        let (db_process, db_message) = conn
+            .build_transaction()
+            .repeatable_read()
+            .read_only()
+            .run::<_, DieselError, _>(|conn| {
+            let db_process: Option<DbProcess> = processes_dsl::processes
+                .filter(processes_dsl::process_id.eq(process_id_in))
+                .first(conn)
+                .optional()?;
+            let db_message: Option<DbMessage> = messages_dsl::messages
+                .filter(messages_dsl::process_id.eq(process_id_in))
+                .order(messages_dsl::row_id.desc())
+                .first(conn)
+                .optional()?;
+            Ok((db_process, db_message))
+        })?;
+
+        let process: Process = match db_process {
+            Some(db_process) => serde_json::from_value(db_process.process_data)?,
+            None => {
+                return Err(StoreErrorType::NotFound(
+                    Entity::Process,
+                    process_id_in.to_string(),
+                ))
+            }
+        };
+        let message = match db_message {
+            Some(db_message) => Some(self.db_message_to_message(conn, &db_message)?),
+            None => None,
+        };
+
+        Ok((process, message))
+    }
+
+    fn process_exists(&self, process_id_in: &str) -> Result<bool, StoreErrorType> {
+        use super::schema::processes::dsl::*;
+        let conn = &mut self.get_conn()?;
+
+        match diesel::select(diesel::dsl::exists(
+            processes.filter(process_id.eq(process_id_in)),
+        ))
+        .get_result::<bool>(conn)
+        {
+            Ok(exists) => Ok(exists),
+            Err(e) => Err(StoreErrorType::from(e)),
+        }
+    }
+
+    /*
+        page through the processes this node hosts by
+        row_id, only the ids are read so no process_data
+        is deserialized. pass the last row_id back in
+        as after_row_id to get the next page
+    */
+    fn list_processes(
+        &self,
+        after_row_id: Option<i32>,
+        limit: i64,
+    ) -> Result<Vec<(i32, String)>, StoreErrorType> {
+        use super::schema::processes::dsl::*;
+        let conn = &mut self.get_conn()?;
+
+        match processes
+            .filter(row_id.gt(after_row_id.unwrap_or(0)))
+            .order(row_id.asc())
+            .limit(limit)
+            .select((row_id, process_id))
+            .load::<(i32, String)>(conn)
+        {
+            Ok(rows) => Ok(rows),
+            Err(e) => Err(StoreErrorType::from(e)),
+        }
+    }
+
+    fn check_existing_message(&self, message: &Message) -> Result<(), StoreErrorType> {
+        let conn = &mut self.get_conn()?;
+        self.check_existing_message_on(conn, message)
+    }
+
+    async fn save_message(
+        &self,
+        message: &Message,
+        bundle_in: &[u8],
+    ) -> Result<SaveOutcome, StoreErrorType> {
+        let saved_ids = match self.save_hook {
+            Some(_) => Some((message.message_id()?, message.process_id()?)),
+            None => None,
+        };
+
+        let store = self.clone();
+        let message = message.clone();
+        let bundle_in = bundle_in.to_vec();
+        let outcome =
+            run_blocking(move || store.save_message_blocking(&message, &bundle_in)).await?;
+
+        // only reached once the save's transaction has committed
+        if let (SaveOutcome::Inserted { .. }, Some((message_id_in, process_id_in))) =
+            (outcome, saved_ids)
+        {
+            self.notify_saved(&message_id_in, &process_id_in);
+        }
+        Ok(outcome)
+    }
+
+    async fn queued_save_message(
+        &self,
+        message: &Message,
+        bundle_in: &[u8],
+    ) -> Result<SaveOutcome, StoreErrorType> {
+        let queue = match &self.ingest_queue {
+            Some(queue) => queue,
+            None => return self.save_message(message, bundle_in).await,
+        };
+
+        let (reply, response) = oneshot::channel();
+        queue
+            .sender_for(&message.process_id()?)
+            .send((message.clone(), bundle_in.to_vec(), reply))
+            .await
+            .map_err(|_| StoreErrorType::DatabaseError("Ingest queue is closed".to_string()))?;
+        response.await.map_err(|_| {
+            StoreErrorType::DatabaseError("Ingest worker stopped before saving".to_string())
+        })?
+    }
+
+    async fn save_messages_batch(&self, batch: Vec<(Message, Vec<u8>)>) -> BatchSaveReport {
+        let store = self.clone();
+        let (report, saved) = match run_blocking(move || {
+            let (report, inserted) = store.save_messages_batch_indexed(&batch);
+            // only the inserted messages are notified, skipped ones were saved before
+            let saved: Vec<(String, String)> = match store.save_hook.is_some() {
+                true => inserted
+                    .into_iter()
+                    .filter_map(|index| {
+                        let message = &batch[index].0;
+                        match (message.message_id(), message.process_id()) {
+                            (Ok(mid), Ok(pid)) => Some((mid, pid)),
+                            _ => None,
+                        }
+                    })
+                    .collect(),
+                false => vec![],
+            };
+            Ok((report, saved))
+        })
+        .await
+        {
+            Ok(result) => result,
+            Err(e) => (
+                BatchSaveReport {
+                    saved: 0,
+                    skipped: 0,
+                    error: Some(e),
+                },
+                vec![],
+            ),
+        };
+
+        for (message_id_in, process_id_in) in saved.iter() {
+            self.notify_saved(message_id_in, process_id_in);
+        }
+        report
+    }
+
+    /*
+        messages on a process carrying the tag name=value,
+        for example Action=Transfer, in the order they were
+        scheduled
+    */
+    fn get_messages_by_tag(
+        &self,
+        process_id_in: &str,
+        tag_name: &str,
+        tag_value: &str,
+        limit: i64,
+    ) -> Result<Vec<Message>, StoreErrorType> {
+        use super::schema::{message_tags, messages};
+        let conn = &mut self.get_conn()?;
+
+        let tagged_row_ids = message_tags::table
+            .filter(message_tags::name.eq(tag_name))
+            .filter(message_tags::value.eq(tag_value))
+            .select(message_tags::message_row_id);
+
+        let db_messages_result: Result<Vec<DbMessage>, DieselError> = messages::table
+            .filter(messages::process_id.eq(process_id_in))
+            .filter(messages::row_id.eq_any(tagged_row_ids))
+            .order(messages::row_id.asc())
+            .limit(limit)
+            .load(conn);
+
+        match db_messages_result {
+            Ok(db_messages) => self.db_messages_to_messages(conn, &db_messages),
+            Err(e) => Err(StoreErrorType::from(e)),
+        }
+    }
+
+    fn get_messages(
+        &self,
+        process_id_in: &str,
+        from: &Option<String>,
+        to: &Option<String>,
+        limit: &Option<i32>,
+        include_total: bool,
+        range: MessageRange,
+    ) -> Result<PaginatedMessages, StoreErrorType> {
+        use super::schema::messages::dsl::*;
+        let conn = &mut self.get_conn()?;
+
+        // Parse the 'from' and 'to' timestamps if they are provided
+        let from_cursor = match from {
+            Some(from_str) => Some(parse_from_cursor(from_str)?),
+            None => None,
+        };
+        let to_timestamp = match to {
+            Some(to_timestamp_str) => Some(
+                to_timestamp_str
+                    .parse::<i64>()
+                    .map_err(StoreErrorType::from)?,
+            ),
+            None => None,
+        };
+
+        // Apply limit, converting Option<i32> to i64 and adding 1 to check for the next page
+        let limit_val = limit.unwrap_or(5000) as i64; // Default limit if none is provided
+
+        // Fetch one extra key to determine if a next page exists
+        let page_keys_result: Result<Vec<(i32, i64)>, DieselError> = messages_page_keys(
+            process_id_in,
+            from_cursor,
+            to_timestamp,
+            range,
+            limit_val + 1,
+        )
+        .load(conn);
+
+        // The count ignores the limit, it is opt in because it scans the whole filter
+        let total_count = if include_total {
+            Some(
+                messages_in_range(process_id_in, from_cursor, to_timestamp, range)
+                    .count()
+                    .get_result::<i64>(conn)?,
+            )
+        } else {
+            None
+        };
+
+        match page_keys_result {
+            Ok(page_keys) => {
+                let has_next_page = page_keys.len() as i64 > limit_val;
+                // Take only up to the limit if there's an extra indicating a next page
+                let page_keys = if has_next_page {
+                    &page_keys[..(limit_val as usize)]
+                } else {
+                    &page_keys[..]
+                };
+
+                // the full rows of the page, put back in its order
+                let page_row_ids: Vec<i32> = page_keys.iter().map(|(r, _)| *r).collect();
+                let mut by_row_id: HashMap<i32, DbMessage> = messages
+                    .filter(row_id.eq_any(&page_row_ids))
+                    .load::<DbMessage>(conn)?
+                    .into_iter()
+                    .map(|m| (m.row_id, m))
+                    .collect();
+                let db_messages: Vec<DbMessage> = page_row_ids
+                    .iter()
+                    .filter_map(|r| by_row_id.remove(r))
+                    .collect();
+
+                let messages_mapped = self.db_messages_to_messages(conn, &db_messages)?;
+
+                /*
+                    the last row's timestamp and row_id, passed back
+                    as from the next page starts right after it even
+                    when more messages share that timestamp
+                */
+                let next_cursor = page_keys.last().map(|(r, t)| format!("{}:{}", t, r));
+                let paginated = PaginatedMessages::from_messages(
+                    messages_mapped,
+                    has_next_page,
+                    total_count,
+                    next_cursor,
+                )?;
+                Ok(paginated)
+            }
+            Err(e) => Err(StoreErrorType::from(e)),
+        }
+    }
+
+    /*
+        every message scheduled in one epoch of a process,
+        in schedule order, for reconciling against a checkpoint.
+        pass the nonce of the last message seen as after_nonce
+        to read the next page.
+    */
+    fn get_messages_in_epoch(
+        &self,
+        process_id_in: &str,
+        epoch_in: i32,
+        after_nonce: &Option<i32>,
+        limit: &Option<i32>,
+    ) -> Result<PaginatedMessages, StoreErrorType> {
+        use super::schema::messages::dsl::*;
+        let conn = &mut self.get_conn()?;
+        let mut query = messages
+            .filter(process_id.eq(process_id_in))
+            .filter(epoch.eq(epoch_in))
+            .into_boxed();
+
+        if let Some(after_nonce) = after_nonce {
+            query = query.filter(nonce.gt(after_nonce));
+        }
+
+        let limit_val = limit.unwrap_or(5000) as i64;
+        let db_messages_result: Result<Vec<DbMessage>, DieselError> = query
+            .order(nonce.asc())
+            .limit(limit_val + 1) // Fetch one extra record to determine if a next page exists
+            .load(conn);
+
+        match db_messages_result {
+            Ok(db_messages) => {
+                let has_next_page = db_messages.len() as i64 > limit_val;
+                let messages_o = if has_next_page {
+                    &db_messages[..(limit_val as usize)]
+                } else {
+                    &db_messages[..]
+                };
+
+                let messages_mapped = self.db_messages_to_messages(conn, messages_o)?;
+                // paged by nonce here, the cursor is the after_nonce for the next page
+                let next_cursor = messages_o.last().map(|m| m.nonce.to_string());
+                let paginated = PaginatedMessages::from_messages(
+                    messages_mapped,
+                    has_next_page,
+                    None,
+                    next_cursor,
+                )?;
+                Ok(paginated)
+            }
+            Err(e) => Err(StoreErrorType::from(e)),
+        }
+    }
+
+    /*
+        every message of a process in (epoch, nonce) order,
+        for replays. rows are read SWEEP_BATCH_SIZE at a time
+        on the blocking pool with their bundles resolved in
+        one query per batch, so only a batch is held in memory
+        however long the process is and polling the stream
+        never blocks an async worker. the stream ends after
+        the first error
+    */
+    fn iter_process_messages(
+        &self,
+        process_id_in: &str,
+    ) -> BoxStream<'static, Result<Message, StoreErrorType>> {
+        let reader = ProcessMessages {
+            store: self.clone(),
+            process_id: process_id_in.to_string(),
+            after: None,
+            buffered: VecDeque::new(),
+            done: false,
+        };
+        stream::unfold(Some(reader), |reader| async move {
+            reader?.next_message().await
+        })
+        .boxed()
+    }
+
+    fn get_message(&self, tx_id: &str) -> Result<Message, StoreErrorType> {
+        let (message, _) = self.get_message_matched(tx_id)?;
+        Ok(message)
+    }
+
+    /*
+        get_message, also reporting whether tx_id was
+        the message id or an assignment id of the row
+    */
+    fn get_message_matched(&self, tx_id: &str) -> Result<(Message, MatchedBy), StoreErrorType> {
+        let conn = &mut self.get_conn()?;
+        self.message_matched_on(conn, tx_id)
+    }
+
+    /*
+        find the message that produced a hash chain value,
+        for tracing breaks in a process's chain
+    */
+    fn get_message_by_hash_chain(
+        &self,
+        process_id_in: &str,
+        hash_chain_in: &str,
+    ) -> Result<Option<Message>, StoreErrorType> {
+        use super::schema::messages::dsl::*;
+        let conn = &mut self.get_conn()?;
+
+        let db_message_result: Result<Option<DbMessage>, DieselError> = messages
+            .filter(process_id.eq(process_id_in))
+            .filter(hash_chain.eq(hash_chain_in))
+            .order(row_id.asc())
+            .first(conn)
+            .optional();
+
+        match db_message_result {
+            Ok(Some(db_message)) => {
+                let message = self.db_message_to_message(conn, &db_message)?;
+                Ok(Some(message))
+            }
+            Ok(None) => Ok(None),
+            Err(e) => Err(StoreErrorType::from(e)),
+        }
+    }
+
+    /*
+        the stored bundle for the same row get_message would
+        return, selected on its own so relays that only forward
+        the bytes skip reading and parsing message_data
+    */
+    fn get_bundle_bytes(&self, tx_id: &str) -> Result<Vec<u8>, StoreErrorType> {
+        use super::schema::messages::dsl::*;
+        let conn = &mut self.get_conn()?;
+
+        let bundle_result: Result<Option<StoredBundle>, DieselError> = messages
+            .filter(message_id.eq(tx_id).or(assignment_id.eq(tx_id)))
+            .order((timestamp.asc(), row_id.asc()))
+            .select((bundle, bundle_hash, bundle_codec))
+            .first(conn)
+            .optional();
+
+        match bundle_result {
+            Ok(Some(stored)) => Ok(resolve_bundles(conn, vec![stored])?.remove(0)),
+            Ok(None) => Err(StoreErrorType::NotFound(Entity::Message, tx_id.to_string())),
+            Err(e) => Err(StoreErrorType::from(e)),
+        }
+    }
+
+    /*
+        length and reader for a download handler that sends
+        Content-Length before the body. bundles only live in
+        postgres here so the reader is over the loaded bytes
+    */
+    fn bundle_size_and_stream(
+        &self,
+        tx_id: &str,
+    ) -> Result<(u64, Box<dyn Read + Send>), StoreErrorType> {
+        let bundle_bytes = self.get_bundle_bytes(tx_id)?;
+        Ok((
+            bundle_bytes.len() as u64,
+            Box::new(Cursor::new(bundle_bytes)),
+        ))
+    }
+
+    /*
+        offset..offset + len of the bundle get_bundle_bytes
+        would return, for HTTP Range requests. postgres cuts
+        the slice so the rest of the bundle is never sent over
+    */
+    fn read_bundle_range(
+        &self,
+        tx_id: &str,
+        offset: u64,
+        len: u64,
+    ) -> Result<Vec<u8>, StoreErrorType> {
+        use super::schema::bundles::dsl as bundles_dsl;
+        use super::schema::messages::dsl::*;
+        let conn = &mut self.get_conn()?;
+
+        // bundles are capped at 1GB by bytea so anything past i32 is out of range anyway
+        let end = match offset.checked_add(len) {
+            Some(end) if end <= i32::MAX as u64 => end,
+            _ => {
+                return Err(StoreErrorType::InvalidRange(format!(
+                    "Range of {} bytes at {} is too large",
+                    len, offset
+                )))
+            }
+        };
+        // substring positions start at 1
+        let start = offset as i32 + 1;
+        let count = len as i32;
+
+        let (row_id_in, stored_hash): (i32, Option<String>) = messages
+            .filter(message_id.eq(tx_id).or(assignment_id.eq(tx_id)))
+            .order((timestamp.asc(), row_id.asc()))
+            .select((row_id, bundle_hash))
+            .first(conn)
+            .optional()?
+            .ok_or_else(|| StoreErrorType::NotFound(Entity::Message, tx_id.to_string()))?;
+
+        let (stored_codec, size, slice): (i16, i32, Vec<u8>) = match stored_hash {
+            Some(hash_in) => bundles_dsl::bundles
+                .filter(bundles_dsl::hash.eq(hash_in))
+                .select((
+                    bundles_dsl::codec,
+                    octet_length(bundles_dsl::bundle),
+                    substring(bundles_dsl::bundle, start, count),
+                ))
+                .first(conn)?,
+            None => messages
+                .filter(row_id.eq(row_id_in))
+                .select((
+                    bundle_codec,
+                    octet_length(bundle.assume_not_null()),
+                    substring(bundle.assume_not_null(), start, count),
+                ))
+                .first(conn)?,
+        };
+
+        if stored_codec == BUNDLE_CODEC_RAW {
+            check_range_end(offset, end, size as u64)?;
+            return Ok(slice);
+        }
+
+        // a compressed bundle can't be cut in postgres, it is decompressed whole here
+        let stored_bundle: StoredBundle = messages
+            .filter(row_id.eq(row_id_in))
+            .select((bundle, bundle_hash, bundle_codec))
+            .first(conn)?;
+        let bundle_bytes = resolve_bundles(conn, vec![stored_bundle])?.remove(0);
+        check_range_end(offset, end, bundle_bytes.len() as u64)?;
+        Ok(bundle_bytes[offset as usize..end as usize].to_vec())
+    }
+
+    /*
+        recompute the ids from every stored bundle for a
+        message_id, the original and any reassignments, and
+        check they match the ids the rows are stored under
+    */
+    fn verify_bundle_id(&self, message_id_in: &str) -> Result<bool, StoreErrorType> {
+        use super::schema::messages::dsl::*;
+        let conn = &mut self.get_conn()?;
+
+        let rows: Vec<(String, Option<String>, StoredBundle)> = messages
+            .filter(message_id.eq(message_id_in))
+            .select((
+                message_id,
+                assignment_id,
+                (bundle, bundle_hash, bundle_codec),
+            ))
+            .load(conn)?;
+
+        if rows.is_empty() {
+            return Err(StoreErrorType::NotFound(
+                Entity::Message,
+                message_id_in.to_string(),
+            ));
+        }
+
+        let stored_bundles =
+            resolve_bundles(conn, rows.iter().map(|(_, _, b)| b.clone()).collect())?;
+        for ((stored_message_id, stored_assignment_id, _), stored_bundle) in
+            rows.into_iter().zip(stored_bundles)
+        {
+            if !bundle_matches_ids(&stored_message_id, &stored_assignment_id, stored_bundle)? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /*
+        verify_bundle_id over a whole process in batches,
+        returns the message_ids with a row whose bundle
+        doesn't match
+    */
+    fn verify_process_bundles(&self, process_id_in: &str) -> Result<Vec<String>, StoreErrorType> {
+        use super::schema::messages::dsl::*;
+        let conn = &mut self.get_conn()?;
+
+        let mut mismatched: Vec<String> = vec![];
+        let mut after = 0;
+        loop {
+            let rows: Vec<(i32, String, Option<String>, StoredBundle)> = messages
+                .filter(process_id.eq(process_id_in))
+                .filter(row_id.gt(after))
+                .order(row_id.asc())
+                .limit(SWEEP_BATCH_SIZE)
+                .select((
+                    row_id,
+                    message_id,
+                    assignment_id,
+                    (bundle, bundle_hash, bundle_codec),
+                ))
+                .load(conn)?;
+
+            let last = match rows.last() {
+                Some((last, _, _, _)) => *last,
+                None => break,
+            };
+
+            let stored_bundles =
+                resolve_bundles(conn, rows.iter().map(|(_, _, _, b)| b.clone()).collect())?;
+            for ((_, stored_message_id, stored_assignment_id, _), stored_bundle) in
+                rows.into_iter().zip(stored_bundles)
+            {
+                if !bundle_matches_ids(&stored_message_id, &stored_assignment_id, stored_bundle)?
+                    && !mismatched.contains(&stored_message_id)
+                {
+                    mismatched.push(stored_message_id);
+                }
+            }
+            after = last;
+        }
+
+        Ok(mismatched)
+    }
+
+    /*
+        message_ids on a process stored more than once with
+        different message data items. reassignments only
+        carry the assignment so they never count, this is
+        for finding the same id written with different bytes
+    */
+    fn find_duplicate_message_bundles(
+        &self,
+        process_id_in: &str,
+    ) -> Result<Vec<String>, StoreErrorType> {
+        use super::schema::messages::dsl::*;
+        let conn = &mut self.get_conn()?;
+
+        let repeated_ids: Vec<String> = messages
+            .filter(process_id.eq(process_id_in))
+            .group_by(message_id)
+            .having(diesel::dsl::count_star().gt(1))
+            .select(message_id)
+            .load(conn)?;
+
+        if repeated_ids.is_empty() {
+            return Ok(vec![]);
+        }
+
+        // every row of every repeated id in one query, grouped by id below
+        let rows: Vec<(String, StoredBundle)> = messages
+            .filter(process_id.eq(process_id_in))
+            .filter(message_id.eq_any(&repeated_ids))
+            .order((message_id.asc(), row_id.asc()))
+            .select((message_id, (bundle, bundle_hash, bundle_codec)))
+            .load(conn)?;
+        let (row_ids, stored): (Vec<String>, Vec<StoredBundle>) = rows.into_iter().unzip();
+
+        let mut item_hashes: HashMap<String, Vec<String>> = HashMap::new();
+        for (repeated_id, stored_bundle) in row_ids.into_iter().zip(resolve_bundles(conn, stored)?)
+        {
+            let hashes = item_hashes.entry(repeated_id).or_default();
+            if let Some(item_hash) = Message::message_item_hash(stored_bundle)? {
+                if !hashes.contains(&item_hash) {
+                    hashes.push(item_hash);
+                }
+            }
+        }
+
+        Ok(repeated_ids
+            .into_iter()
+            .filter(|repeated_id| {
+                item_hashes
+                    .get(repeated_id)
+                    .is_some_and(|hashes| hashes.len() > 1)
+            })
+            .collect())
+    }
+
+    /*
+        every row sharing a message_id, the original message
+        followed by any later assignments of it
+    */
+    fn get_assignments_for_message(
+        &self,
+        message_id_in: &str,
+    ) -> Result<Vec<Message>, StoreErrorType> {
+        use super::schema::messages::dsl::*;
+        let conn = &mut self.get_conn()?;
+
+        let db_messages_result: Result<Vec<DbMessage>, DieselError> = messages
+            .filter(message_id.eq(message_id_in))
+            .order((timestamp.asc(), row_id.asc()))
+            .load(conn);
+
+        match db_messages_result {
+            Ok(db_messages) => self.db_messages_to_messages(conn, &db_messages),
+            Err(e) => Err(StoreErrorType::from(e)),
+        }
+    }
+
+    /*
+        resolve an assignment back to the message it
+        assigns without loading the row's data
+    */
+    fn get_message_id_for_assignment(
+        &self,
+        assignment_id_in: &str,
+    ) -> Result<String, StoreErrorType> {
+        use super::schema::messages::dsl::*;
+        let conn = &mut self.get_conn()?;
+
+        let message_id_result: Result<Option<String>, DieselError> = messages
+            .filter(assignment_id.eq(assignment_id_in))
+            .select(message_id)
+            .first(conn)
+            .optional();
+
+        match message_id_result {
+            Ok(Some(message_id_out)) => Ok(message_id_out),
+            Ok(None) => Err(StoreErrorType::NotFound(
+                Entity::Assignment,
+                assignment_id_in.to_string(),
+            )),
+            Err(e) => Err(StoreErrorType::from(e)),
         }
     }
 
@@ -307,27 +2157,639 @@ impl DataStore for StoreClient {
             .order(row_id.desc())
             .first::<DbMessage>(conn);
 
-        match latest_db_message_result {
-            Ok(db_message) => {
-                // Deserialize the message_data into Message
-                let message_val: serde_json::Value =
-                    serde_json::from_value(db_message.message_data)
-                        .map_err(|e| StoreErrorType::from(e))?;
+        match latest_db_message_result {
+            Ok(db_message) => {
+                // message_data, or the bundle when it wasn't stored
+                let message: Message = self.db_message_to_message(conn, &db_message)?;
+
+                Ok(Some(message))
+            }
+            Err(DieselError::NotFound) => Ok(None), // No messages found
+            Err(e) => Err(StoreErrorType::from(e)),
+        }
+    }
+
+    /*
+        walk every message on the node in insertion order for
+        backups. pages are keyed on row_id rather than OFFSET so
+        each page costs the same and ties can't drop or repeat
+        rows. pass the returned row_id back in as after_row_id,
+        None means there was nothing left to read.
+    */
+    fn get_messages_for_export(
+        &self,
+        after_row_id: i32,
+        limit: i64,
+    ) -> Result<(Vec<ExportedMessage>, Option<i32>), StoreErrorType> {
+        use super::schema::messages::dsl::*;
+        let conn = &mut self.get_conn()?;
+
+        let db_rows_result = messages
+            .filter(row_id.gt(after_row_id))
+            .order(row_id.asc())
+            .limit(limit)
+            .select((
+                row_id,
+                message_id,
+                assignment_id,
+                process_id,
+                timestamp,
+                (bundle, bundle_hash, bundle_codec),
+            ))
+            .load::<(i32, String, Option<String>, String, i64, StoredBundle)>(conn);
+
+        match db_rows_result {
+            Ok(db_rows) => {
+                let last_row_id = db_rows.last().map(|row| row.0);
+                let stored_bundles =
+                    resolve_bundles(conn, db_rows.iter().map(|row| row.5.clone()).collect())?;
+                let exported = db_rows
+                    .into_iter()
+                    .zip(stored_bundles)
+                    .map(|((_, m_id, a_id, p_id, ts, _), b)| (m_id, a_id, p_id, ts, b))
+                    .collect();
+                Ok((exported, last_row_id))
+            }
+            Err(e) => Err(StoreErrorType::from(e)),
+        }
+    }
+
+    /*
+        get_messages_for_export without the bundle, for
+        dry runs and counts that only need to enumerate
+        the rows. pages the same way so the two can be
+        used interchangeably
+    */
+    fn get_message_keys_for_export(
+        &self,
+        after_row_id: i32,
+        limit: i64,
+    ) -> Result<(Vec<ExportedMessageKey>, Option<i32>), StoreErrorType> {
+        use super::schema::messages::dsl::*;
+        let conn = &mut self.get_conn()?;
+
+        let db_rows_result = messages
+            .filter(row_id.gt(after_row_id))
+            .order(row_id.asc())
+            .limit(limit)
+            .select((row_id, message_id, assignment_id, process_id, timestamp))
+            .load::<(i32, String, Option<String>, String, i64)>(conn);
+
+        match db_rows_result {
+            Ok(db_rows) => {
+                let last_row_id = db_rows.last().map(|row| row.0);
+                let keys = db_rows
+                    .into_iter()
+                    .map(|(_, m_id, a_id, p_id, ts)| (m_id, a_id, p_id, ts))
+                    .collect();
+                Ok((keys, last_row_id))
+            }
+            Err(e) => Err(StoreErrorType::from(e)),
+        }
+    }
+
+    /*
+        poll for new messages on a process. pass the last
+        returned row_id back in as after, a follower is caught
+        up once last_row_id reaches max_row_id. the max is read
+        first and bounds the page so the two always agree
+    */
+    fn get_messages_after_row_id(
+        &self,
+        process_id_in: &str,
+        after: i32,
+        limit: i64,
+    ) -> Result<TailedMessages, StoreErrorType> {
+        use super::schema::messages::dsl::*;
+        let conn = &mut self.get_conn()?;
+
+        let max_row_id = messages
+            .filter(process_id.eq(process_id_in))
+            .select(diesel::dsl::max(row_id))
+            .first::<Option<i32>>(conn)?;
+
+        let db_messages: Vec<DbMessage> = match max_row_id {
+            Some(max_id) => messages
+                .filter(process_id.eq(process_id_in))
+                .filter(row_id.gt(after))
+                .filter(row_id.le(max_id))
+                .order(row_id.asc())
+                .limit(limit)
+                .load(conn)?,
+            None => vec![],
+        };
+
+        let last_row_id = db_messages.last().map(|m| m.row_id);
+        Ok(TailedMessages {
+            messages: self.db_messages_to_messages(conn, &db_messages)?,
+            last_row_id,
+            max_row_id,
+        })
+    }
+
+    /*
+        get_messages_after_row_id across every process on
+        the node, for building a global feed
+    */
+    fn get_latest_messages_global(
+        &self,
+        after_row_id: i32,
+        limit: i64,
+    ) -> Result<TailedMessages, StoreErrorType> {
+        use super::schema::messages::dsl::*;
+        let conn = &mut self.get_conn()?;
+
+        let max_row_id = messages
+            .select(diesel::dsl::max(row_id))
+            .first::<Option<i32>>(conn)?;
+
+        let db_messages: Vec<DbMessage> = match max_row_id {
+            Some(max_id) => messages
+                .filter(row_id.gt(after_row_id))
+                .filter(row_id.le(max_id))
+                .order(row_id.asc())
+                .limit(limit)
+                .load(conn)?,
+            None => vec![],
+        };
+
+        let last_row_id = db_messages.last().map(|m| m.row_id);
+        Ok(TailedMessages {
+            messages: self.db_messages_to_messages(conn, &db_messages)?,
+            last_row_id,
+            max_row_id,
+        })
+    }
+
+    /*
+        get_messages_after_row_id over a set of processes in
+        one query, for indexers following several at once.
+        an empty set returns an empty page
+    */
+    fn get_messages_multi(
+        &self,
+        process_ids: &[String],
+        after_row_id: i32,
+        limit: i64,
+    ) -> Result<TailedMessages, StoreErrorType> {
+        use super::schema::messages::dsl::*;
+
+        if process_ids.is_empty() {
+            return Ok(TailedMessages {
+                messages: vec![],
+                last_row_id: None,
+                max_row_id: None,
+            });
+        }
+
+        let conn = &mut self.get_conn()?;
+
+        let max_row_id = messages
+            .filter(process_id.eq_any(process_ids))
+            .select(diesel::dsl::max(row_id))
+            .first::<Option<i32>>(conn)?;
+
+        let db_messages: Vec<DbMessage> = match max_row_id {
+            Some(max_id) => messages
+                .filter(process_id.eq_any(process_ids))
+                .filter(row_id.gt(after_row_id))
+                .filter(row_id.le(max_id))
+                .order(row_id.asc())
+                .limit(limit)
+                .load(conn)?,
+            None => vec![],
+        };
+
+        let last_row_id = db_messages.last().map(|m| m.row_id);
+        Ok(TailedMessages {
+            messages: self.db_messages_to_messages(conn, &db_messages)?,
+            last_row_id,
+            max_row_id,
+        })
+    }
+
+    /*
+        messages by the wall clock time this node wrote them,
+        independent of the timestamps in the process schedule
+    */
+    fn get_recently_inserted(
+        &self,
+        since: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<Vec<Message>, StoreErrorType> {
+        use super::schema::messages::dsl::*;
+        let conn = &mut self.get_conn()?;
+
+        let db_messages_result: Result<Vec<DbMessage>, DieselError> = messages
+            .filter(inserted_at.ge(since))
+            .order((inserted_at.asc(), row_id.asc()))
+            .limit(limit)
+            .load(conn);
+
+        match db_messages_result {
+            Ok(db_messages) => self.db_messages_to_messages(conn, &db_messages),
+            Err(e) => Err(StoreErrorType::from(e)),
+        }
+    }
+
+    /*
+        total bytes of every bundle stored for a process,
+        summed from bundle_size so no bundle has to be read
+    */
+    fn get_process_storage_bytes(&self, process_id_in: &str) -> Result<i64, StoreErrorType> {
+        use super::schema::messages::dsl::*;
+        let conn = &mut self.get_conn()?;
+
+        match messages
+            .filter(process_id.eq(process_id_in))
+            .select(diesel::dsl::sum(bundle_size))
+            .first::<Option<i64>>(conn)
+        {
+            Ok(total) => Ok(total.unwrap_or(0)),
+            Err(e) => Err(StoreErrorType::from(e)),
+        }
+    }
+
+    /*
+        (bucket_start, count) for a process's messages with
+        from <= timestamp < to, grouped into bucket_seconds
+        wide buckets. timestamps are in milliseconds, empty
+        buckets are left out
+    */
+    fn get_message_rate(
+        &self,
+        process_id_in: &str,
+        bucket_seconds: i64,
+        from: i64,
+        to: i64,
+    ) -> Result<Vec<(i64, i64)>, StoreErrorType> {
+        let bucket_millis = match bucket_seconds.checked_mul(1000) {
+            Some(millis) if millis > 0 => millis,
+            _ => {
+                return Err(StoreErrorType::InvalidRange(format!(
+                    "Invalid bucket size {}s",
+                    bucket_seconds
+                )))
+            }
+        };
+        let conn = &mut self.get_conn()?;
+
+        let buckets: Vec<RateBucket> = diesel::sql_query(
+            "SELECT (timestamp / $2) * $2 AS bucket_start, COUNT(*) AS message_count \
+             FROM messages WHERE process_id = $1 AND timestamp >= $3 AND timestamp < $4 \
+             GROUP BY 1 ORDER BY 1",
+        )
+        .bind::<Text, _>(process_id_in)
+        .bind::<BigInt, _>(bucket_millis)
+        .bind::<BigInt, _>(from)
+        .bind::<BigInt, _>(to)
+        .load(conn)?;
+
+        Ok(buckets
+            .into_iter()
+            .map(|b| (b.bucket_start, b.message_count))
+            .collect())
+    }
+
+    /*
+        number of stored messages on the node, or on a
+        single process when one is given
+    */
+    fn get_message_count(&self, process_id_in: Option<&str>) -> Result<i64, StoreErrorType> {
+        use super::schema::messages::dsl::*;
+        let conn = &mut self.get_conn()?;
+
+        let mut query = messages.into_boxed();
+        if let Some(process_id_in) = process_id_in {
+            query = query.filter(process_id.eq(process_id_in));
+        }
+
+        match query.count().get_result::<i64>(conn) {
+            Ok(count) => Ok(count),
+            Err(e) => Err(StoreErrorType::from(e)),
+        }
+    }
+
+    /*
+        processes whose newest message is older than the
+        cutoff, oldest first. Processes that never received
+        a message have no activity row and aren't listed
+    */
+    fn get_dormant_processes(&self, older_than: i64) -> Result<Vec<String>, StoreErrorType> {
+        use super::schema::process_activity::dsl::*;
+        let conn = &mut self.get_conn()?;
+
+        match process_activity
+            .filter(last_timestamp.lt(older_than))
+            .order(last_timestamp.asc())
+            .select(process_id)
+            .load::<String>(conn)
+        {
+            Ok(process_ids) => Ok(process_ids),
+            Err(e) => Err(StoreErrorType::from(e)),
+        }
+    }
+
+    /*
+        rebuild everything derived from a process's messages,
+        message_tags, bundle_size and process_activity, from
+        the stored rows. safe to run repeatedly, each batch
+        replaces what it derived before. returns the number
+        of messages reindexed
+    */
+    fn reindex_process(&self, process_id_in: &str) -> Result<usize, StoreErrorType> {
+        use super::schema::message_tags::dsl as message_tags_dsl;
+        use super::schema::messages::dsl::*;
+        let conn = &mut self.get_conn()?;
+
+        let mut reindexed = 0;
+        let mut after = 0;
+        loop {
+            let db_messages: Vec<DbMessage> = messages
+                .filter(process_id.eq(process_id_in))
+                .filter(row_id.gt(after))
+                .order(row_id.asc())
+                .limit(SWEEP_BATCH_SIZE)
+                .load(conn)?;
+
+            let last = match db_messages.last() {
+                Some(db_message) => db_message.row_id,
+                None => break,
+            };
+
+            let parsed: Vec<(i32, Message)> = db_messages
+                .iter()
+                .map(|db_message| db_message.row_id)
+                .zip(self.db_messages_to_messages(conn, &db_messages)?)
+                .collect();
+            let row_ids: Vec<i32> = parsed.iter().map(|(id, _)| *id).collect();
+            let new_tags: Vec<NewMessageTag> = parsed
+                .iter()
+                .flat_map(|(id, message)| {
+                    let tags = match &message.message {
+                        Some(m) => m.tags.as_slice(),
+                        None => &[],
+                    };
+                    tags.iter().map(|tag| NewMessageTag {
+                        message_row_id: *id,
+                        name: &tag.name,
+                        value: &tag.value,
+                    })
+                })
+                .collect();
+
+            conn.transaction::<(), DieselError, _>(|conn| {
+                diesel::delete(
+                    message_tags_dsl::message_tags
+                        .filter(message_tags_dsl::message_row_id.eq_any(&row_ids)),
+                )
+                .execute(conn)?;
+                if !new_tags.is_empty() {
+                    diesel::insert_into(message_tags_dsl::message_tags)
+                        .values(&new_tags)
+                        .execute(conn)?;
+                }
+                // rows moved to the bundles table or compressed keep the size recorded at save
+                diesel::update(
+                    messages
+                        .filter(row_id.eq_any(&row_ids))
+                        .filter(bundle.is_not_null())
+                        .filter(bundle_codec.eq(BUNDLE_CODEC_RAW)),
+                )
+                .set(bundle_size.eq(octet_length(bundle.assume_not_null())))
+                .execute(conn)?;
+                Ok(())
+            })?;
+
+            reindexed += row_ids.len();
+            after = last;
+        }
+
+        // set rather than GREATEST, this is the value to trust
+        let latest: Option<i64> = messages
+            .filter(process_id.eq(process_id_in))
+            .select(diesel::dsl::max(timestamp))
+            .first(conn)?;
+        match latest {
+            Some(latest) => {
+                diesel::sql_query(
+                    "INSERT INTO process_activity (process_id, last_timestamp) VALUES ($1, $2) \
+                     ON CONFLICT (process_id) DO UPDATE SET last_timestamp = EXCLUDED.last_timestamp",
+                )
+                .bind::<Text, _>(process_id_in)
+                .bind::<BigInt, _>(latest)
+                .execute(conn)?;
+            }
+            None => {
+                use super::schema::process_activity::dsl as process_activity_dsl;
+                diesel::delete(
+                    process_activity_dsl::process_activity
+                        .filter(process_activity_dsl::process_id.eq(process_id_in)),
+                )
+                .execute(conn)?;
+            }
+        }
+
+        Ok(reindexed)
+    }
+
+    /*
+        reindex_process over a page of list_processes, returns
+        the messages reindexed and the row_id to pass back in
+        for the next page, None once every process is done
+    */
+    fn reindex_processes(
+        &self,
+        after_row_id: Option<i32>,
+        limit: i64,
+    ) -> Result<(usize, Option<i32>), StoreErrorType> {
+        let page = self.list_processes(after_row_id, limit)?;
+
+        let mut reindexed = 0;
+        for (_, process_id_in) in page.iter() {
+            reindexed += self
+                .reindex_process(process_id_in)
+                .map_err(|e| with_context(e, &format!("Error reindexing {}", process_id_in)))?;
+        }
+
+        let next = match page.len() as i64 == limit {
+            true => page.last().map(|(row_id, _)| *row_id),
+            false => None,
+        };
+        Ok((reindexed, next))
+    }
+
+    /*
+        moves the bundles of messages saved before BUNDLE_TABLE
+        was set into the bundles table, a page at a time so it
+        can run alongside traffic. returns the number of
+        messages moved and the row_id to continue after
+    */
+    fn move_bundles_to_table(
+        &self,
+        after_row_id: i32,
+        limit: i64,
+    ) -> Result<(usize, Option<i32>), StoreErrorType> {
+        use super::schema::messages::dsl::*;
+        let conn = &mut self.get_conn()?;
+
+        let page: Vec<(i32, Vec<u8>, i16)> = messages
+            .filter(row_id.gt(after_row_id))
+            .filter(bundle.is_not_null())
+            .order(row_id.asc())
+            .limit(limit)
+            .select((row_id, bundle.assume_not_null(), bundle_codec))
+            .load(conn)?;
+
+        // the key is the hash of the raw bundle, compressed ones move as they are
+        let mut hashes: Vec<String> = vec![];
+        for (_, b, c) in page.iter() {
+            hashes.push(match *c {
+                BUNDLE_CODEC_RAW => bundle_hash_of(b),
+                _ => bundle_hash_of(&decode_bundle(b.clone(), *c)?),
+            });
+        }
+        let new_bundles: Vec<NewBundle> = page
+            .iter()
+            .zip(hashes.iter())
+            .map(|((_, b, c), h)| NewBundle {
+                hash: h,
+                bundle: b,
+                codec: *c,
+            })
+            .collect();
+
+        conn.transaction::<(), DieselError, _>(|conn| {
+            if !new_bundles.is_empty() {
+                diesel::insert_into(super::schema::bundles::table)
+                    .values(&new_bundles)
+                    .on_conflict(super::schema::bundles::hash)
+                    .do_nothing()
+                    .execute(conn)?;
+            }
+
+            for ((row_id_in, _, _), hash_in) in page.iter().zip(hashes.iter()) {
+                diesel::update(messages.filter(row_id.eq(row_id_in)))
+                    .set((
+                        bundle.eq(None::<Vec<u8>>),
+                        bundle_hash.eq(hash_in),
+                        bundle_codec.eq(BUNDLE_CODEC_RAW),
+                    ))
+                    .execute(conn)?;
+            }
+            Ok(())
+        })?;
+
+        let next = match page.len() as i64 == limit {
+            true => page.last().map(|(r, _, _)| *r),
+            false => None,
+        };
+        Ok((page.len(), next))
+    }
+
+    /*
+        compresses the bundles of messages saved before
+        COMPRESS_BUNDLES was set, inline ones and the rows
+        they share in the bundles table, a page at a time
+        like move_bundles_to_table. bundles zstd can't
+        shrink stay raw. returns the number of messages
+        looked at and the row_id to continue after
+    */
+    fn compress_stored_bundles(
+        &self,
+        after_row_id: i32,
+        limit: i64,
+    ) -> Result<(usize, Option<i32>), StoreErrorType> {
+        use super::schema::bundles::dsl as bundles_dsl;
+        use super::schema::messages::dsl::*;
+        let conn = &mut self.get_conn()?;
+
+        let page: Vec<(i32, Option<Vec<u8>>, Option<String>)> = messages
+            .filter(row_id.gt(after_row_id))
+            .filter(bundle_codec.eq(BUNDLE_CODEC_RAW))
+            .order(row_id.asc())
+            .limit(limit)
+            .select((row_id, bundle, bundle_hash))
+            .load(conn)?;
 
-                let message: Message = Message::from_val(&message_val, db_message.bundle.clone())?;
+        let hashes: Vec<&String> = page.iter().filter_map(|(_, _, h)| h.as_ref()).collect();
+        let shared: Vec<(String, Vec<u8>)> = match hashes.is_empty() {
+            true => vec![],
+            false => bundles_dsl::bundles
+                .filter(bundles_dsl::hash.eq_any(hashes))
+                .filter(bundles_dsl::codec.eq(BUNDLE_CODEC_RAW))
+                .select((bundles_dsl::hash, bundles_dsl::bundle))
+                .load(conn)?,
+        };
 
-                Ok(Some(message))
+        // compressed before the transaction so it isn't held open for it
+        let inline: Vec<(i32, Vec<u8>)> = page
+            .iter()
+            .filter_map(|(r, b, _)| Some((*r, compress_bundle(b.as_ref()?)?)))
+            .collect();
+        let shared: Vec<(String, Vec<u8>)> = shared
+            .into_iter()
+            .filter_map(|(h, b)| Some((h, compress_bundle(&b)?)))
+            .collect();
+
+        conn.transaction::<(), DieselError, _>(|conn| {
+            for (row_id_in, compressed) in inline.iter() {
+                diesel::update(messages.filter(row_id.eq(row_id_in)))
+                    .set((bundle.eq(compressed), bundle_codec.eq(BUNDLE_CODEC_ZSTD)))
+                    .execute(conn)?;
             }
-            Err(DieselError::NotFound) => Ok(None), // No messages found
+            for (hash_in, compressed) in shared.iter() {
+                diesel::update(bundles_dsl::bundles.filter(bundles_dsl::hash.eq(hash_in)))
+                    .set((
+                        bundles_dsl::bundle.eq(compressed),
+                        bundles_dsl::codec.eq(BUNDLE_CODEC_ZSTD),
+                    ))
+                    .execute(conn)?;
+            }
+            Ok(())
+        })?;
+
+        let next = match page.len() as i64 == limit {
+            true => page.last().map(|(r, _, _)| *r),
+            false => None,
+        };
+        Ok((page.len(), next))
+    }
+
+    /*
+        how far a mirror that has seen up to nonce_in
+        is behind this node for a process
+    */
+    fn count_messages_after_nonce(
+        &self,
+        process_id_in: &str,
+        nonce_in: i32,
+    ) -> Result<i64, StoreErrorType> {
+        use super::schema::messages::dsl::*;
+        let conn = &mut self.get_conn()?;
+
+        match messages
+            .filter(process_id.eq(process_id_in))
+            .filter(nonce.gt(nonce_in))
+            .count()
+            .get_result::<i64>(conn)
+        {
+            Ok(count) => Ok(count),
             Err(e) => Err(StoreErrorType::from(e)),
         }
     }
 
+    /*
+        map a process to its scheduler and count it there in
+        the same transaction. a process that is already mapped
+        is left alone and not counted again, false then
+    */
     fn save_process_scheduler(
         &self,
         process_scheduler: &ProcessScheduler,
-    ) -> Result<String, StoreErrorType> {
+    ) -> Result<bool, StoreErrorType> {
         use super::schema::process_schedulers::dsl::*;
+        use super::schema::schedulers::dsl as schedulers_dsl;
         let conn = &mut self.get_conn()?;
 
         let new_process_scheduler = NewProcessScheduler {
@@ -335,15 +2797,30 @@ impl DataStore for StoreClient {
             scheduler_row_id: &process_scheduler.scheduler_row_id,
         };
 
-        match diesel::insert_into(process_schedulers)
-            .values(&new_process_scheduler)
-            .on_conflict(process_id)
-            .do_nothing()
-            .execute(conn)
-        {
-            Ok(_) => Ok("saved".to_string()),
-            Err(e) => Err(StoreErrorType::from(e)),
-        }
+        conn.transaction::<bool, StoreErrorType, _>(|conn| {
+            let inserted = diesel::insert_into(process_schedulers)
+                .values(&new_process_scheduler)
+                .on_conflict(process_id)
+                .do_nothing()
+                .execute(conn)?;
+            if inserted == 0 {
+                return Ok(false);
+            }
+
+            let incremented = diesel::update(
+                schedulers_dsl::schedulers
+                    .filter(schedulers_dsl::row_id.eq(process_scheduler.scheduler_row_id)),
+            )
+            .set(schedulers_dsl::process_count.eq(schedulers_dsl::process_count + 1))
+            .execute(conn)?;
+            if incremented == 0 {
+                return Err(StoreErrorType::NotFound(
+                    Entity::Scheduler,
+                    process_scheduler.scheduler_row_id.to_string(),
+                ));
+            }
+            Ok(true)
+        })
     }
 
     fn get_process_scheduler(
@@ -368,7 +2845,8 @@ impl DataStore for StoreClient {
                 Ok(process_scheduler)
             }
             Ok(None) => Err(StoreErrorType::NotFound(
-                "Process scheduler not found".to_string(),
+                Entity::ProcessScheduler,
+                process_id_in.to_string(),
             )),
             Err(e) => Err(StoreErrorType::from(e)),
         }
@@ -394,6 +2872,34 @@ impl DataStore for StoreClient {
         }
     }
 
+    /*
+        insert a whole scheduler list in one statement, urls
+        that already exist are skipped. returns how many
+        schedulers were new
+    */
+    fn save_schedulers_batch(&self, schedulers_in: &[Scheduler]) -> Result<usize, StoreErrorType> {
+        use super::schema::schedulers::dsl::*;
+        let conn = &mut self.get_conn()?;
+
+        let new_schedulers: Vec<NewScheduler> = schedulers_in
+            .iter()
+            .map(|scheduler| NewScheduler {
+                url: &scheduler.url,
+                process_count: &scheduler.process_count,
+            })
+            .collect();
+
+        match diesel::insert_into(schedulers)
+            .values(&new_schedulers)
+            .on_conflict(url)
+            .do_nothing()
+            .execute(conn)
+        {
+            Ok(inserted) => Ok(inserted),
+            Err(e) => Err(StoreErrorType::from(e)),
+        }
+    }
+
     fn update_scheduler(&self, scheduler: &Scheduler) -> Result<String, StoreErrorType> {
         use super::schema::schedulers::dsl::*;
         let conn = &mut self.get_conn()?;
@@ -411,6 +2917,124 @@ impl DataStore for StoreClient {
         }
     }
 
+    /*
+        adjust process_count in the database itself so
+        concurrent assignments can't overwrite each other
+    */
+    fn increment_scheduler_process_count(
+        &self,
+        row_id_in: i32,
+        delta: i32,
+    ) -> Result<String, StoreErrorType> {
+        use super::schema::schedulers::dsl::*;
+        let conn = &mut self.get_conn()?;
+
+        match diesel::update(schedulers.filter(row_id.eq(row_id_in)))
+            .set(process_count.eq(process_count + delta))
+            .execute(conn)
+        {
+            Ok(0) => Err(StoreErrorType::NotFound(
+                Entity::Scheduler,
+                row_id_in.to_string(),
+            )),
+            Ok(_) => Ok("updated".to_string()),
+            Err(e) => Err(StoreErrorType::from(e)),
+        }
+    }
+
+    /*
+        remove a retired scheduler, refused while any process
+        is still mapped to it so reassign those first. the
+        row is locked so nothing can be mapped to it between
+        the check and the delete
+    */
+    fn delete_scheduler(&self, row_id_in: i32) -> Result<String, StoreErrorType> {
+        use super::schema::process_schedulers::dsl as process_schedulers_dsl;
+        use super::schema::schedulers::dsl::*;
+        let conn = &mut self.get_conn()?;
+
+        conn.transaction::<String, StoreErrorType, _>(|conn| {
+            let locked: Option<i32> = schedulers
+                .filter(row_id.eq(row_id_in))
+                .select(row_id)
+                .for_update()
+                .first(conn)
+                .optional()?;
+            if locked.is_none() {
+                return Err(StoreErrorType::NotFound(
+                    Entity::Scheduler,
+                    row_id_in.to_string(),
+                ));
+            }
+
+            let mapped: i64 = process_schedulers_dsl::process_schedulers
+                .filter(process_schedulers_dsl::scheduler_row_id.eq(row_id_in))
+                .count()
+                .get_result(conn)?;
+            if mapped > 0 {
+                return Err(StoreErrorType::NotAllowed(format!(
+                    "Scheduler still has {} processes",
+                    mapped
+                )));
+            }
+
+            diesel::delete(schedulers.filter(row_id.eq(row_id_in))).execute(conn)?;
+            Ok("deleted".to_string())
+        })
+    }
+
+    /*
+        move a process to another scheduler, the mapping
+        and both process_counts change in one transaction
+    */
+    fn reassign_process(
+        &self,
+        process_id_in: &str,
+        new_scheduler_row_id: i32,
+    ) -> Result<String, StoreErrorType> {
+        use super::schema::process_schedulers::dsl as process_schedulers_dsl;
+        use super::schema::schedulers::dsl::*;
+        let conn = &mut self.get_conn()?;
+
+        conn.transaction::<String, StoreErrorType, _>(|conn| {
+            let old_scheduler_row_id: i32 = process_schedulers_dsl::process_schedulers
+                .filter(process_schedulers_dsl::process_id.eq(process_id_in))
+                .select(process_schedulers_dsl::scheduler_row_id)
+                .for_update()
+                .first(conn)
+                .optional()?
+                .ok_or_else(|| {
+                    StoreErrorType::NotFound(Entity::ProcessScheduler, process_id_in.to_string())
+                })?;
+
+            if old_scheduler_row_id == new_scheduler_row_id {
+                return Ok("unchanged".to_string());
+            }
+
+            let incremented = diesel::update(schedulers.filter(row_id.eq(new_scheduler_row_id)))
+                .set(process_count.eq(process_count + 1))
+                .execute(conn)?;
+            if incremented == 0 {
+                return Err(StoreErrorType::NotFound(
+                    Entity::Scheduler,
+                    new_scheduler_row_id.to_string(),
+                ));
+            }
+            diesel::update(schedulers.filter(row_id.eq(old_scheduler_row_id)))
+                .set(process_count.eq(process_count - 1))
+                .execute(conn)?;
+
+            diesel::update(
+                process_schedulers_dsl::process_schedulers
+                    .filter(process_schedulers_dsl::process_id.eq(process_id_in)),
+            )
+            .set(process_schedulers_dsl::scheduler_row_id.eq(new_scheduler_row_id))
+            .execute(conn)?;
+
+            Ok("reassigned".to_string())
+        })
+    }
+
     fn get_scheduler(&self, row_id_in: &i32) -> Result<Scheduler, StoreErrorType> {
         use super::schema::schedulers::dsl::*;
         let conn = &mut self.get_conn()?;
@@ -429,53 +3053,830 @@ impl DataStore for StoreClient {
                 };
                 Ok(scheduler)
             }
-            Ok(None) => Err(StoreErrorType::NotFound("Scheduler not found".to_string())),
+            Ok(None) => Err(StoreErrorType::NotFound(
+                Entity::Scheduler,
+                row_id_in.to_string(),
+            )),
+            Err(e) => Err(StoreErrorType::from(e)),
+        }
+    }
+
+    fn get_scheduler_by_url(&self, url_in: &String) -> Result<Scheduler, StoreErrorType> {
+        use super::schema::schedulers::dsl::*;
+        let conn = &mut self.get_conn()?;
+
+        let db_scheduler_result: Result<Option<DbScheduler>, DieselError> =
+            schedulers.filter(url.eq(url_in)).first(conn).optional();
+
+        match db_scheduler_result {
+            Ok(Some(db_scheduler)) => {
+                let scheduler: Scheduler = Scheduler {
+                    row_id: Some(db_scheduler.row_id),
+                    url: db_scheduler.url,
+                    process_count: db_scheduler.process_count,
+                };
+                Ok(scheduler)
+            }
+            Ok(None) => Err(StoreErrorType::NotFound(
+                Entity::Scheduler,
+                url_in.to_string(),
+            )),
+            Err(e) => Err(StoreErrorType::from(e)),
+        }
+    }
+
+    fn get_all_schedulers(&self) -> Result<Vec<Scheduler>, StoreErrorType> {
+        use super::schema::schedulers::dsl::*;
+        let conn = &mut self.get_conn()?;
+
+        match schedulers.order(row_id.asc()).load::<DbScheduler>(conn) {
+            Ok(db_schedulers) => Ok(db_schedulers_to_schedulers(db_schedulers)),
+            Err(e) => Err(StoreErrorType::from(e)),
+        }
+    }
+
+    /*
+        every known migration by name and whether it has
+        been applied, read from the same source run_migrations
+        uses so a MIGRATIONS_DIR override is reported too
+    */
+    fn migration_status(&self) -> Result<Vec<(String, bool)>, StoreErrorType> {
+        let conn = &mut self.get_conn()?;
+        let applied = conn.applied_migrations().map_err(|e| {
+            StoreErrorType::DatabaseError(format!("Error reading applied migrations: {}", e))
+        })?;
+
+        let known: Vec<Box<dyn Migration<Pg>>> = match &self.migrations_dir {
+            Some(dir) => FileBasedMigrations::from_path(dir)
+                .map_err(|e| e.to_string())
+                .and_then(|m| m.migrations().map_err(|e| e.to_string())),
+            None => MIGRATIONS.migrations().map_err(|e| e.to_string()),
+        }
+        .map_err(|e| StoreErrorType::DatabaseError(format!("Error reading migrations: {}", e)))?;
+
+        Ok(known
+            .iter()
+            .map(|m| (m.name().to_string(), applied.contains(&m.name().version())))
+            .collect())
+    }
+
+    /*
+        same as get_all_schedulers but ordered by url, row ids
+        differ between databases so this is the ordering to use
+        when comparing scheduler lists across nodes
+    */
+    fn get_all_schedulers_by_url(&self) -> Result<Vec<Scheduler>, StoreErrorType> {
+        use super::schema::schedulers::dsl::*;
+        let conn = &mut self.get_conn()?;
+
+        match schedulers
+            .order((url.asc(), row_id.asc()))
+            .load::<DbScheduler>(conn)
+        {
+            Ok(db_schedulers) => Ok(db_schedulers_to_schedulers(db_schedulers)),
+            Err(e) => Err(StoreErrorType::from(e)),
+        }
+    }
+
+    /*
+        the scheduler new processes should be assigned to,
+        ties go to the oldest scheduler
+    */
+    fn get_least_loaded_scheduler(&self) -> Result<Scheduler, StoreErrorType> {
+        use super::schema::schedulers::dsl::*;
+        let conn = &mut self.get_conn()?;
+
+        let db_scheduler_result: Result<Option<DbScheduler>, DieselError> = schedulers
+            .order((process_count.asc(), row_id.asc()))
+            .first(conn)
+            .optional();
+
+        match db_scheduler_result {
+            Ok(Some(db_scheduler)) => {
+                let scheduler: Scheduler = Scheduler {
+                    row_id: Some(db_scheduler.row_id),
+                    url: db_scheduler.url,
+                    process_count: db_scheduler.process_count,
+                };
+                Ok(scheduler)
+            }
+            Ok(None) => Err(StoreErrorType::NotFound(Entity::Scheduler, String::new())),
             Err(e) => Err(StoreErrorType::from(e)),
         }
     }
 
-    fn get_scheduler_by_url(&self, url_in: &String) -> Result<Scheduler, StoreErrorType> {
-        use super::schema::schedulers::dsl::*;
-        let conn = &mut self.get_conn()?;
+    /*
+        remove a process along with its messages and its
+        router mapping. this is only for test environments
+        and spam cleanup so it is disabled unless
+        ENABLE_PROCESS_DELETION is set
+    */
+    fn delete_process(&self, process_id_in: &str) -> Result<DeleteReport, StoreErrorType> {
+        use super::schema::messages::dsl as messages_dsl;
+        use super::schema::process_activity::dsl as process_activity_dsl;
+        use super::schema::process_schedulers::dsl as process_schedulers_dsl;
+        use super::schema::processes::dsl as processes_dsl;
+        use super::schema::schedulers::dsl as schedulers_dsl;
+
+        if !self.enable_process_deletion {
+            return Err(StoreErrorType::NotAllowed(
+                "Process deletion is disabled".to_string(),
+            ));
+        }
+
+        let conn = &mut self.get_conn()?;
+
+        conn.transaction::<DeleteReport, StoreErrorType, _>(|conn| {
+            let bundle_hashes: Vec<String> = messages_dsl::messages
+                .filter(messages_dsl::process_id.eq(process_id_in))
+                .filter(messages_dsl::bundle_hash.is_not_null())
+                .select(messages_dsl::bundle_hash.assume_not_null())
+                .distinct()
+                .load(conn)?;
+
+            // messages reference the process row so they go first
+            let messages_deleted = diesel::delete(
+                messages_dsl::messages.filter(messages_dsl::process_id.eq(process_id_in)),
+            )
+            .execute(conn)?;
+
+            // drop the bundles no other process's messages point at
+            if !bundle_hashes.is_empty() {
+                diesel::sql_query(
+                    "DELETE FROM bundles b WHERE b.hash = ANY($1) \
+                     AND NOT EXISTS (SELECT 1 FROM messages m WHERE m.bundle_hash = b.hash)",
+                )
+                .bind::<Array<Text>, _>(&bundle_hashes)
+                .execute(conn)?;
+            }
+
+            let scheduler_row_ids: Vec<i32> = diesel::delete(
+                process_schedulers_dsl::process_schedulers
+                    .filter(process_schedulers_dsl::process_id.eq(process_id_in)),
+            )
+            .returning(process_schedulers_dsl::scheduler_row_id)
+            .get_results(conn)?;
+            let process_schedulers_deleted = scheduler_row_ids.len();
+
+            // the scheduler no longer carries this process
+            for scheduler_row_id in &scheduler_row_ids {
+                diesel::update(
+                    schedulers_dsl::schedulers.filter(schedulers_dsl::row_id.eq(scheduler_row_id)),
+                )
+                .set(schedulers_dsl::process_count.eq(schedulers_dsl::process_count - 1))
+                .execute(conn)?;
+            }
+
+            let processes_deleted = diesel::delete(
+                processes_dsl::processes.filter(processes_dsl::process_id.eq(process_id_in)),
+            )
+            .execute(conn)?;
+
+            diesel::delete(
+                process_activity_dsl::process_activity
+                    .filter(process_activity_dsl::process_id.eq(process_id_in)),
+            )
+            .execute(conn)?;
+
+            // an unknown process rolls back whatever stray rows it had
+            if processes_deleted == 0 && process_schedulers_deleted == 0 {
+                return Err(StoreErrorType::NotFound(
+                    Entity::Process,
+                    process_id_in.to_string(),
+                ));
+            }
+
+            Ok(DeleteReport {
+                processes_deleted,
+                messages_deleted,
+                process_schedulers_deleted,
+            })
+        })
+    }
+
+    /*
+        move a process and everything stored under it to
+        new_id, for processes ingested under the wrong id.
+        only the process_id columns change. bundles are
+        signed and message_data and process_data mirror
+        them, so none of those can be rewritten. the rename
+        is refused unless every one of them already names
+        new_id, so it can correct the columns but never
+        leave them disagreeing with the payloads. messages
+        reference the processes row so it is copied to
+        new_id first and the old one dropped once nothing
+        points at it
+    */
+    fn rename_process(&self, old_id: &str, new_id: &str) -> Result<RenameReport, StoreErrorType> {
+        use super::schema::messages::dsl as messages_dsl;
+        use super::schema::process_activity::dsl as process_activity_dsl;
+        use super::schema::process_schedulers::dsl as process_schedulers_dsl;
+        use super::schema::processes::dsl as processes_dsl;
+        use diesel::dsl::exists;
+
+        let conn = &mut self.get_conn()?;
+
+        conn.transaction::<RenameReport, StoreErrorType, _>(|conn| {
+            let taken = diesel::select(exists(
+                processes_dsl::processes.filter(processes_dsl::process_id.eq(new_id)),
+            ))
+            .get_result::<bool>(conn)?
+                || diesel::select(exists(
+                    messages_dsl::messages.filter(messages_dsl::process_id.eq(new_id)),
+                ))
+                .get_result::<bool>(conn)?
+                || diesel::select(exists(
+                    process_schedulers_dsl::process_schedulers
+                        .filter(process_schedulers_dsl::process_id.eq(new_id)),
+                ))
+                .get_result::<bool>(conn)?;
+            if taken {
+                return Err(StoreErrorType::NotAllowed(format!(
+                    "Process {} already exists",
+                    new_id
+                )));
+            }
+
+            let process_data_in = processes_dsl::processes
+                .filter(processes_dsl::process_id.eq(old_id))
+                .select(processes_dsl::process_data)
+                .first::<serde_json::Value>(conn)
+                .optional()?;
+            if let Some(process_data_in) = process_data_in {
+                if process_data_in.get("process_id").and_then(|v| v.as_str()) != Some(new_id) {
+                    return Err(StoreErrorType::NotAllowed(format!(
+                        "Process {} is not stored as {}, its data can't be renamed",
+                        old_id, new_id
+                    )));
+                }
+            }
+
+            let mut after_row_id = 0;
+            loop {
+                let page: Vec<DbMessage> = messages_dsl::messages
+                    .filter(messages_dsl::process_id.eq(old_id))
+                    .filter(messages_dsl::row_id.gt(after_row_id))
+                    .order(messages_dsl::row_id.asc())
+                    .limit(SWEEP_BATCH_SIZE)
+                    .load(conn)?;
+                let last_row_id = match page.last() {
+                    Some(last) => last.row_id,
+                    None => break,
+                };
+                for message in self.db_messages_to_messages(conn, &page)? {
+                    if message.process_id()? != new_id {
+                        return Err(StoreErrorType::NotAllowed(format!(
+                            "Message {} is signed for process {}, it can't be renamed to {}",
+                            message.message_id()?,
+                            message.process_id()?,
+                            new_id
+                        )));
+                    }
+                }
+                after_row_id = last_row_id;
+            }
+
+            let processes_renamed = diesel::sql_query(
+                "INSERT INTO processes (process_id, process_data, bundle) \
+                 SELECT $2, process_data, bundle FROM processes WHERE process_id = $1",
+            )
+            .bind::<Text, _>(old_id)
+            .bind::<Text, _>(new_id)
+            .execute(conn)?;
+
+            let messages_renamed =
+                diesel::update(messages_dsl::messages.filter(messages_dsl::process_id.eq(old_id)))
+                    .set(messages_dsl::process_id.eq(new_id))
+                    .execute(conn)?;
+
+            let process_schedulers_renamed = diesel::update(
+                process_schedulers_dsl::process_schedulers
+                    .filter(process_schedulers_dsl::process_id.eq(old_id)),
+            )
+            .set(process_schedulers_dsl::process_id.eq(new_id))
+            .execute(conn)?;
+
+            if processes_renamed == 0 && process_schedulers_renamed == 0 {
+                return Err(StoreErrorType::NotFound(
+                    Entity::Process,
+                    old_id.to_string(),
+                ));
+            }
+
+            diesel::update(
+                process_activity_dsl::process_activity
+                    .filter(process_activity_dsl::process_id.eq(old_id)),
+            )
+            .set(process_activity_dsl::process_id.eq(new_id))
+            .execute(conn)?;
+
+            diesel::delete(processes_dsl::processes.filter(processes_dsl::process_id.eq(old_id)))
+                .execute(conn)?;
+
+            Ok(RenameReport {
+                processes_renamed,
+                messages_renamed,
+                process_schedulers_renamed,
+            })
+        })
+    }
+}
+
+// the comparison get_messages applies to timestamp for one end of a MessageRange
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Bound {
+    Gt,
+    Ge,
+    Lt,
+    Le,
+}
+
+impl Bound {
+    fn from_edge(range: MessageRange) -> Bound {
+        match (range.order, range.from_inclusive) {
+            (SortOrder::Asc, false) => Bound::Gt,
+            (SortOrder::Asc, true) => Bound::Ge,
+            (SortOrder::Desc, false) => Bound::Lt,
+            (SortOrder::Desc, true) => Bound::Le,
+        }
+    }
+
+    fn to_edge(range: MessageRange) -> Bound {
+        match (range.order, range.to_inclusive) {
+            (SortOrder::Asc, false) => Bound::Lt,
+            (SortOrder::Asc, true) => Bound::Le,
+            (SortOrder::Desc, false) => Bound::Gt,
+            (SortOrder::Desc, true) => Bound::Ge,
+        }
+    }
+
+    fn filter<'a>(
+        self,
+        query: super::schema::messages::BoxedQuery<'a, Pg>,
+        edge: i64,
+    ) -> super::schema::messages::BoxedQuery<'a, Pg> {
+        use super::schema::messages::dsl::timestamp;
+        match self {
+            Bound::Gt => query.filter(timestamp.gt(edge)),
+            Bound::Ge => query.filter(timestamp.ge(edge)),
+            Bound::Lt => query.filter(timestamp.lt(edge)),
+            Bound::Le => query.filter(timestamp.le(edge)),
+        }
+    }
+}
+
+// the messages of a process get_messages pages through and counts
+fn messages_in_range<'a>(
+    process_id_in: &'a str,
+    from_cursor: Option<(i64, Option<i32>)>,
+    to_timestamp: Option<i64>,
+    range: MessageRange,
+) -> super::schema::messages::BoxedQuery<'a, Pg> {
+    use super::schema::messages::dsl::*;
+    let mut query = messages.filter(process_id.eq(process_id_in)).into_boxed();
+    match from_cursor {
+        Some((from_timestamp, Some(from_row_id))) => {
+            query = keyset_after(query, range.order, from_timestamp, from_row_id);
+        }
+        Some((from_timestamp, None)) => {
+            query = Bound::from_edge(range).filter(query, from_timestamp);
+        }
+        None => (),
+    }
+    if let Some(to_timestamp) = to_timestamp {
+        query = Bound::to_edge(range).filter(query, to_timestamp);
+    }
+    query
+}
+
+/*
+    the (row_id, timestamp) of one page of messages_in_range.
+    it only reads columns of the (process_id, timestamp, row_id)
+    index so postgres answers it with an index only scan, the
+    full rows are then fetched by row_id for just the page
+*/
+fn messages_page_keys<'a>(
+    process_id_in: &'a str,
+    from_cursor: Option<(i64, Option<i32>)>,
+    to_timestamp: Option<i64>,
+    range: MessageRange,
+    limit_in: i64,
+) -> super::schema::messages::BoxedQuery<'a, Pg, (Int4, BigInt)> {
+    use super::schema::messages::dsl::*;
+    let query = messages_in_range(process_id_in, from_cursor, to_timestamp, range);
+    // row_id breaks timestamp ties so messages sharing one keep a stable order
+    let ordered = match range.order {
+        SortOrder::Asc => query.order((timestamp.asc(), row_id.asc())),
+        SortOrder::Desc => query.order((timestamp.desc(), row_id.desc())),
+    };
+    ordered.select((row_id, timestamp)).limit(limit_in)
+}
+
+/*
+    from is either a plain timestamp or a next_cursor
+    of timestamp:row_id handed out by get_messages
+*/
+fn parse_from_cursor(from: &str) -> Result<(i64, Option<i32>), StoreErrorType> {
+    match from.split_once(':') {
+        Some((timestamp_str, row_id_str)) => Ok((
+            timestamp_str.parse::<i64>()?,
+            Some(row_id_str.parse::<i32>()?),
+        )),
+        None => Ok((from.parse::<i64>()?, None)),
+    }
+}
+
+/*
+    the rows after (timestamp, row_id) in the page order.
+    the outer timestamp bound is redundant but lets the
+    index range scan instead of filtering the whole process
+*/
+fn keyset_after<'a>(
+    query: super::schema::messages::BoxedQuery<'a, Pg>,
+    order: SortOrder,
+    timestamp_in: i64,
+    row_id_in: i32,
+) -> super::schema::messages::BoxedQuery<'a, Pg> {
+    use super::schema::messages::dsl::{row_id, timestamp};
+    match order {
+        SortOrder::Asc => query.filter(
+            timestamp
+                .ge(timestamp_in)
+                .and(timestamp.gt(timestamp_in).or(row_id.gt(row_id_in))),
+        ),
+        SortOrder::Desc => query.filter(
+            timestamp
+                .le(timestamp_in)
+                .and(timestamp.lt(timestamp_in).or(row_id.lt(row_id_in))),
+        ),
+    }
+}
+
+// a schema name as a quoted postgres identifier
+fn quote_ident(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+// returns the number of bytes written
+fn write_export_frame(
+    writer: &mut impl Write,
+    format: ExportFormat,
+    exported: ExportedMessage,
+) -> Result<u64, StoreErrorType> {
+    let (message_id_in, assignment_id_in, process_id_in, timestamp_in, bundle_in) = exported;
+    let mut frame = ExportFrame {
+        message_id: message_id_in,
+        assignment_id: assignment_id_in,
+        process_id: process_id_in,
+        timestamp: timestamp_in,
+        bundle: None,
+    };
+    match format {
+        ExportFormat::Ndjson => {
+            frame.bundle = Some(base64_url::encode(&bundle_in));
+            let mut line = serde_json::to_vec(&frame)?;
+            line.push(b'\n');
+            writer.write_all(&line)?;
+            Ok(line.len() as u64)
+        }
+        ExportFormat::LengthPrefixed => {
+            let header = serde_json::to_vec(&frame)?;
+            writer.write_all(&(header.len() as u32).to_be_bytes())?;
+            writer.write_all(&header)?;
+            writer.write_all(&(bundle_in.len() as u64).to_be_bytes())?;
+            writer.write_all(&bundle_in)?;
+            Ok((4 + header.len() + 8 + bundle_in.len()) as u64)
+        }
+    }
+}
+
+// reader behind the iter_process_messages stream
+struct ProcessMessages {
+    store: StoreClient,
+    process_id: String,
+    // (epoch, nonce) of the last message read
+    after: Option<(i32, i32)>,
+    buffered: VecDeque<Message>,
+    done: bool,
+}
+
+impl ProcessMessages {
+    fn read_batch(&mut self) -> Result<(), StoreErrorType> {
+        use super::schema::messages::dsl::*;
+        let conn = &mut self.store.get_conn()?;
+
+        let mut query = messages
+            .filter(process_id.eq(&self.process_id))
+            .into_boxed();
+        if let Some((after_epoch, after_nonce)) = self.after {
+            query = query.filter(
+                epoch
+                    .gt(after_epoch)
+                    .or(epoch.eq(after_epoch).and(nonce.gt(after_nonce))),
+            );
+        }
+        let db_messages: Vec<DbMessage> = query
+            .order((epoch.asc(), nonce.asc()))
+            .limit(SWEEP_BATCH_SIZE)
+            .load(conn)?;
+
+        if let Some(last) = db_messages.last() {
+            self.after = Some((last.epoch, last.nonce));
+        }
+        // a short batch was the last one, no need to ask again
+        if (db_messages.len() as i64) < SWEEP_BATCH_SIZE {
+            self.done = true;
+        }
+        self.buffered = self
+            .store
+            .db_messages_to_messages(conn, &db_messages)?
+            .into();
+        Ok(())
+    }
+
+    /*
+        the next message and the reader to carry on with,
+        None for the reader after an error so the stream ends
+    */
+    async fn next_message(mut self) -> Option<(Result<Message, StoreErrorType>, Option<Self>)> {
+        if self.buffered.is_empty() && !self.done {
+            let read = run_blocking(move || {
+                let mut reader = self;
+                reader.read_batch().map(|()| reader)
+            })
+            .await;
+            self = match read {
+                Ok(reader) => reader,
+                Err(e) => return Some((Err(e), None)),
+            };
+        }
+        let message = self.buffered.pop_front()?;
+        Some((Ok(message), Some(self)))
+    }
+}
 
-        let db_scheduler_result: Result<Option<DbScheduler>, DieselError> =
-            schedulers.filter(url.eq(url_in)).first(conn).optional();
+// a frame and its bundle, Err for one that was read but can't be used
+type ReadFrame = Result<(ExportFrame, Vec<u8>), String>;
 
-        match db_scheduler_result {
-            Ok(Some(db_scheduler)) => {
-                let scheduler: Scheduler = Scheduler {
-                    row_id: Some(db_scheduler.row_id),
-                    url: db_scheduler.url,
-                    process_count: db_scheduler.process_count,
-                };
-                Ok(scheduler)
+/*
+    the next frame of an export, None at the end of the
+    stream. a ReadFrame Err doesn't stop the stream, the
+    next frame can still be read after it
+*/
+fn read_export_frame(
+    reader: &mut impl BufRead,
+    format: ExportFormat,
+) -> Result<Option<ReadFrame>, StoreErrorType> {
+    match format {
+        ExportFormat::Ndjson => {
+            let mut line = String::new();
+            while line.trim().is_empty() {
+                line.clear();
+                if reader.read_line(&mut line)? == 0 {
+                    return Ok(None);
+                }
             }
-            Ok(None) => Err(StoreErrorType::NotFound("Scheduler not found".to_string())),
-            Err(e) => Err(StoreErrorType::from(e)),
+            let frame = serde_json::from_str::<ExportFrame>(&line).map_err(|e| e.to_string());
+            Ok(Some(frame.and_then(|mut frame| {
+                let encoded = frame.bundle.take().ok_or("frame has no bundle")?;
+                let bundle_in = base64_url::decode(&encoded).map_err(|e| e.to_string())?;
+                Ok((frame, bundle_in))
+            })))
+        }
+        ExportFormat::LengthPrefixed => {
+            if reader.fill_buf()?.is_empty() {
+                return Ok(None);
+            }
+            let header = read_length_prefixed(reader, 4)?;
+            let bundle_in = read_length_prefixed(reader, 8)?;
+            let frame = serde_json::from_slice::<ExportFrame>(&header).map_err(|e| e.to_string());
+            Ok(Some(frame.map(|frame| (frame, bundle_in))))
         }
     }
+}
 
-    fn get_all_schedulers(&self) -> Result<Vec<Scheduler>, StoreErrorType> {
-        use super::schema::schedulers::dsl::*;
-        let conn = &mut self.get_conn()?;
+// a big endian length of prefix_len bytes, then that many bytes
+fn read_length_prefixed(
+    reader: &mut impl BufRead,
+    prefix_len: usize,
+) -> Result<Vec<u8>, StoreErrorType> {
+    let mut prefix = [0u8; 8];
+    reader.read_exact(&mut prefix[8 - prefix_len..])?;
+    let len = u64::from_be_bytes(prefix);
 
-        match schedulers.order(row_id.asc()).load::<DbScheduler>(conn) {
-            Ok(db_schedulers) => {
-                let schedulers_out: Vec<Scheduler> = db_schedulers
-                    .into_iter()
-                    .map(|db_scheduler| Scheduler {
-                        row_id: Some(db_scheduler.row_id),
-                        url: db_scheduler.url,
-                        process_count: db_scheduler.process_count,
-                    })
-                    .collect();
-                Ok(schedulers_out)
-            }
-            Err(e) => Err(StoreErrorType::from(e)),
+    let mut bytes = vec![];
+    reader.take(len).read_to_end(&mut bytes)?;
+    if (bytes.len() as u64) < len {
+        return Err(StoreErrorType::from(std::io::Error::new(
+            ErrorKind::UnexpectedEof,
+            "export stream ends inside a frame",
+        )));
+    }
+    Ok(bytes)
+}
+
+fn frame_matches_message(frame: &ExportFrame, message: &Message) -> bool {
+    message.message_id().ok().as_ref() == Some(&frame.message_id)
+        && message.process_id().ok().as_ref() == Some(&frame.process_id)
+        && message.stored_assignment_id() == frame.assignment_id
+}
+
+// None when zstd doesn't make the bundle smaller
+fn compress_bundle(bundle_in: &[u8]) -> Option<Vec<u8>> {
+    match zstd::bulk::compress(bundle_in, ZSTD_LEVEL) {
+        Ok(compressed) if compressed.len() < bundle_in.len() => Some(compressed),
+        _ => None,
+    }
+}
+
+fn decode_bundle(stored: Vec<u8>, codec_in: i16) -> Result<Vec<u8>, StoreErrorType> {
+    match codec_in {
+        BUNDLE_CODEC_RAW => Ok(stored),
+        BUNDLE_CODEC_ZSTD => zstd::stream::decode_all(stored.as_slice()).map_err(|e| {
+            StoreErrorType::DatabaseError(format!("Bundle could not be decompressed: {}", e))
+        }),
+        _ => Err(StoreErrorType::DatabaseError(format!(
+            "Unknown bundle codec {}",
+            codec_in
+        ))),
+    }
+}
+
+fn check_range_end(offset: u64, end: u64, size: u64) -> Result<(), StoreErrorType> {
+    match end > size {
+        true => Err(StoreErrorType::InvalidRange(format!(
+            "Range {}-{} is past the end of the {} byte bundle",
+            offset, end, size
+        ))),
+        false => Ok(()),
+    }
+}
+
+// the key of a bundle in the bundles table
+fn bundle_hash_of(bundle_in: &[u8]) -> String {
+    base64_url::encode(&Sha256::digest(bundle_in))
+}
+
+/*
+    the row_id of the message stored on new_message's
+    process, epoch and nonce, with true when it holds the
+    same ids so saving new_message again is a repeat
+*/
+fn stored_on_key(
+    conn: &mut PgConnection,
+    new_message: &NewMessage,
+) -> Result<Option<(i32, bool)>, DieselError> {
+    use super::schema::messages::dsl::*;
+
+    let stored = messages
+        .filter(process_id.eq(new_message.process_id))
+        .filter(epoch.eq(new_message.epoch))
+        .filter(nonce.eq(new_message.nonce))
+        .select((row_id, message_id, assignment_id))
+        .first::<(i32, String, Option<String>)>(conn)
+        .optional()?;
+    Ok(
+        stored.map(|(stored_row_id, stored_message_id, stored_assignment_id)| {
+            (
+                stored_row_id,
+                stored_message_id == new_message.message_id
+                    && stored_assignment_id == new_message.assignment_id,
+            )
+        }),
+    )
+}
+
+// GREATEST so an older timestamp saved late can't move it back
+fn touch_process_activity(
+    conn: &mut PgConnection,
+    process_id_in: &str,
+    timestamp_in: i64,
+) -> Result<usize, DieselError> {
+    diesel::sql_query(
+        "INSERT INTO process_activity (process_id, last_timestamp) VALUES ($1, $2) \
+         ON CONFLICT (process_id) DO UPDATE SET last_timestamp = \
+         GREATEST(process_activity.last_timestamp, EXCLUDED.last_timestamp)",
+    )
+    .bind::<Text, _>(process_id_in)
+    .bind::<BigInt, _>(timestamp_in)
+    .execute(conn)
+}
+
+async fn run_blocking<T, F>(f: F) -> Result<T, StoreErrorType>
+where
+    F: FnOnce() -> Result<T, StoreErrorType> + Send + 'static,
+    T: Send + 'static,
+{
+    tokio::task::spawn_blocking(f).await.map_err(|e| {
+        StoreErrorType::DatabaseError(format!("Database task failed to complete: {}", e))
+    })?
+}
+
+/*
+    prefix the message of a StoreErrorType while
+    keeping its variant so callers can still match on it
+*/
+fn with_context(error: StoreErrorType, context: &str) -> StoreErrorType {
+    match error {
+        StoreErrorType::DatabaseError(m) => {
+            StoreErrorType::DatabaseError(format!("{}: {}", context, m))
         }
+        // the id is left as is for callers that match on it
+        StoreErrorType::NotFound(entity, id) => StoreErrorType::NotFound(entity, id),
+        StoreErrorType::JsonError(m) => StoreErrorType::JsonError(format!("{}: {}", context, m)),
+        StoreErrorType::EnvVarError(m) => {
+            StoreErrorType::EnvVarError(format!("{}: {}", context, m))
+        }
+        StoreErrorType::IntError(m) => StoreErrorType::IntError(format!("{}: {}", context, m)),
+        StoreErrorType::MessageExists(m) => {
+            StoreErrorType::MessageExists(format!("{}: {}", context, m))
+        }
+        StoreErrorType::NotAllowed(m) => StoreErrorType::NotAllowed(format!("{}: {}", context, m)),
+        StoreErrorType::PoolTimeout(m) => {
+            StoreErrorType::PoolTimeout(format!("{}: {}", context, m))
+        }
+        StoreErrorType::QueryTimeout(m) => {
+            StoreErrorType::QueryTimeout(format!("{}: {}", context, m))
+        }
+        StoreErrorType::InvalidRange(m) => {
+            StoreErrorType::InvalidRange(format!("{}: {}", context, m))
+        }
+        StoreErrorType::IoError(m) => StoreErrorType::IoError(format!("{}: {}", context, m)),
     }
 }
 
+fn db_schedulers_to_schedulers(db_schedulers: Vec<DbScheduler>) -> Vec<Scheduler> {
+    db_schedulers
+        .into_iter()
+        .map(|db_scheduler| Scheduler {
+            row_id: Some(db_scheduler.row_id),
+            url: db_scheduler.url,
+            process_count: db_scheduler.process_count,
+        })
+        .collect()
+}
+
+/*
+    ids are the hash of each item's signature, so
+    rebuilding the message from the bundle recomputes
+    them. assignment only rows take their message_id
+    from a tag, their assignment id is still checked
+*/
+fn bundle_matches_ids(
+    message_id_in: &str,
+    assignment_id_in: &Option<String>,
+    bundle_in: Vec<u8>,
+) -> Result<bool, StoreErrorType> {
+    let rebuilt = Message::from_bundle_bytes(bundle_in)?;
+    Ok(rebuilt.message_id()? == message_id_in
+        && rebuilt.stored_assignment_id() == *assignment_id_in)
+}
+
+// a messages row's bundle column, bundle_hash and bundle_codec
+type StoredBundle = (Option<Vec<u8>>, Option<String>, i16);
+
+/*
+    rows saved with BUNDLE_TABLE set have a NULL bundle
+    and the hash of their row in bundles. this fills the
+    bytes back in, looking every hash up in one query,
+    and decompresses whatever was stored compressed
+*/
+fn resolve_bundles(
+    conn: &mut PgConnection,
+    stored: Vec<StoredBundle>,
+) -> Result<Vec<Vec<u8>>, StoreErrorType> {
+    use super::schema::bundles::dsl::*;
+
+    let hashes: Vec<String> = stored
+        .iter()
+        .filter(|(b, _, _)| b.is_none())
+        .filter_map(|(_, h, _)| h.clone())
+        .collect();
+    let loaded: HashMap<String, (Vec<u8>, i16)> = match hashes.is_empty() {
+        true => HashMap::new(),
+        false => bundles
+            .filter(hash.eq_any(&hashes))
+            .select((hash, (bundle, codec)))
+            .load::<(String, (Vec<u8>, i16))>(conn)?
+            .into_iter()
+            .collect(),
+    };
+
+    stored
+        .into_iter()
+        .map(|(b, h, c)| match (b, h) {
+            (Some(b), _) => decode_bundle(b, c),
+            (None, Some(h)) => match loaded.get(&h) {
+                Some((b, c)) => decode_bundle(b.clone(), *c),
+                None => Err(StoreErrorType::NotFound(Entity::Bundle, h)),
+            },
+            (None, None) => Err(StoreErrorType::DatabaseError(
+                "Message row has neither a bundle nor a bundle_hash".to_string(),
+            )),
+        })
+        .collect()
+}
+
 #[derive(Queryable, Selectable)]
 #[diesel(table_name = super::schema::processes)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
@@ -494,12 +3895,16 @@ pub struct DbMessage {
     pub process_id: String,
     pub message_id: String,
     pub assignment_id: Option<String>,
-    pub message_data: serde_json::Value,
+    pub message_data: Option<serde_json::Value>,
     pub epoch: i32,
     pub nonce: i32,
     pub timestamp: i64,
-    pub bundle: Vec<u8>,
+    pub bundle: Option<Vec<u8>>,
     pub hash_chain: String,
+    pub inserted_at: DateTime<Utc>,
+    pub bundle_size: i32,
+    pub bundle_hash: Option<String>,
+    pub bundle_codec: i16,
 }
 
 #[derive(Insertable)]
@@ -507,13 +3912,40 @@ pub struct DbMessage {
 pub struct NewMessage<'a> {
     pub process_id: &'a str,
     pub message_id: &'a str,
-    pub assignment_id: &'a str,
-    pub message_data: serde_json::Value,
-    pub bundle: &'a [u8],
+    pub assignment_id: Option<String>,
+    pub message_data: Option<serde_json::Value>,
+    pub bundle: Option<&'a [u8]>,
     pub epoch: &'a i32,
     pub nonce: &'a i32,
     pub timestamp: &'a i64,
     pub hash_chain: &'a str,
+    pub bundle_size: i32,
+    pub bundle_hash: Option<String>,
+    pub bundle_codec: i16,
+}
+
+#[derive(QueryableByName)]
+pub struct RateBucket {
+    #[diesel(sql_type = BigInt)]
+    pub bucket_start: i64,
+    #[diesel(sql_type = BigInt)]
+    pub message_count: i64,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = super::schema::bundles)]
+pub struct NewBundle<'a> {
+    pub hash: &'a str,
+    pub bundle: &'a [u8],
+    pub codec: i16,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = super::schema::message_tags)]
+pub struct NewMessageTag<'a> {
+    pub message_row_id: i32,
+    pub name: &'a str,
+    pub value: &'a str,
 }
 
 #[derive(Insertable)]
@@ -555,3 +3987,1070 @@ pub struct NewProcessScheduler<'a> {
     pub process_id: &'a str,
     pub scheduler_row_id: &'a i32,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bundlr_sdk::tags::Tag;
+    use diesel::query_builder::{AstPass, Query, QueryFragment, QueryId};
+
+    const TIMESTAMPS: [i64; 6] = [10, 20, 20, 30, 40, 40];
+
+    // SuLog sets up env_logger, which can only happen once per test binary
+    struct TestLog;
+
+    impl Log for TestLog {
+        fn log(&self, _message: String) {}
+        fn error(&self, _message: String) {}
+    }
+
+    fn test_store() -> StoreClient {
+        StoreClient::new(Arc::new(TestLog)).unwrap()
+    }
+
+    // a scheduler at url_in carrying process_id_in, as the router records it
+    fn scheduled_on(store: &StoreClient, process_id_in: &str, url_in: &str) -> Scheduler {
+        store
+            .save_scheduler(&Scheduler {
+                row_id: None,
+                url: url_in.to_string(),
+                process_count: 0,
+            })
+            .unwrap();
+        let scheduler = store.get_scheduler_by_url(&url_in.to_string()).unwrap();
+        store
+            .save_process_scheduler(&ProcessScheduler {
+                row_id: None,
+                process_id: process_id_in.to_string(),
+                scheduler_row_id: scheduler.row_id.unwrap(),
+            })
+            .unwrap();
+        scheduler
+    }
+
+    /*
+        a store with a fresh process holding a message at
+        each of TIMESTAMPS, the range tests run get_messages
+        against it so they cover the sql it actually sends
+    */
+    fn seeded_store(label: &str) -> (StoreClient, String) {
+        let store = test_store();
+        store.run_migrations().unwrap();
+        let process = format!("{}-{}", label, Utc::now().timestamp_nanos_opt().unwrap());
+        diesel::sql_query(
+            "INSERT INTO processes (process_id, process_data, bundle) VALUES ($1, '{}', '')",
+        )
+        .bind::<Text, _>(&process)
+        .execute(&mut store.get_conn().unwrap())
+        .unwrap();
+        let batch: Vec<(Message, Vec<u8>)> = TIMESTAMPS
+            .iter()
+            .enumerate()
+            .map(|(i, t)| {
+                let id = format!("{}-{}", process, i);
+                (unsigned_message(&process, &id, i as i32, *t), vec![0])
+            })
+            .collect();
+        assert_eq!(
+            store.save_messages_batch_indexed(&batch).0.saved,
+            TIMESTAMPS.len()
+        );
+        (store, process)
+    }
+
+    // the timestamps get_messages returns for a window
+    fn window(
+        (store, process): &(StoreClient, String),
+        range: MessageRange,
+        from: Option<i64>,
+        to: Option<i64>,
+    ) -> Vec<i64> {
+        store
+            .get_messages(
+                process,
+                &from.map(|f| f.to_string()),
+                &to.map(|t| t.to_string()),
+                &Some(100),
+                false,
+                range,
+            )
+            .unwrap()
+            .edges
+            .iter()
+            .map(|edge| edge.node.timestamp().unwrap())
+            .collect()
+    }
+
+    fn stitched(seeded: &(StoreClient, String), range: MessageRange, edges: &[i64]) -> Vec<i64> {
+        edges
+            .windows(2)
+            .flat_map(|w| window(seeded, range, Some(w[0]), Some(w[1])))
+            .collect()
+    }
+
+    #[test]
+    fn test_bound_sql() {
+        use super::super::schema::messages::dsl::messages;
+        let sql = |bound: Bound| {
+            diesel::debug_query::<Pg, _>(&bound.filter(messages.into_boxed(), 20)).to_string()
+        };
+        let default = MessageRange::default();
+        assert!(sql(Bound::from_edge(default)).contains(r#""messages"."timestamp" > $1"#));
+        assert!(sql(Bound::to_edge(default)).contains(r#""messages"."timestamp" <= $1"#));
+        let desc = MessageRange {
+            order: SortOrder::Desc,
+            from_inclusive: true,
+            to_inclusive: false,
+        };
+        assert!(sql(Bound::from_edge(desc)).contains(r#""messages"."timestamp" <= $1"#));
+        assert!(sql(Bound::to_edge(desc)).contains(r#""messages"."timestamp" > $1"#));
+    }
+
+    #[test]
+    #[ignore = "needs a postgres su database in DATABASE_URL"]
+    fn test_default_range_bounds() {
+        let seeded = seeded_store("default-range");
+        let range = MessageRange::default();
+        assert_eq!(window(&seeded, range, Some(20), Some(40)), vec![30, 40, 40]);
+        assert_eq!(window(&seeded, range, Some(10), Some(20)), vec![20, 20]);
+        assert_eq!(window(&seeded, range, None, Some(10)), vec![10]);
+    }
+
+    #[test]
+    #[ignore = "needs a postgres su database in DATABASE_URL"]
+    fn test_inclusive_flags_on_boundary() {
+        let seeded = seeded_store("inclusive-flags");
+        let both = MessageRange {
+            order: SortOrder::Asc,
+            from_inclusive: true,
+            to_inclusive: true,
+        };
+        assert_eq!(
+            window(&seeded, both, Some(20), Some(40)),
+            vec![20, 20, 30, 40, 40]
+        );
+
+        let neither = MessageRange {
+            order: SortOrder::Asc,
+            from_inclusive: false,
+            to_inclusive: false,
+        };
+        assert_eq!(window(&seeded, neither, Some(20), Some(40)), vec![30]);
+
+        let desc = MessageRange {
+            order: SortOrder::Desc,
+            from_inclusive: true,
+            to_inclusive: false,
+        };
+        assert_eq!(window(&seeded, desc, Some(40), Some(20)), vec![40, 40, 30]);
+    }
+
+    // a message with just the tags a save reads, nothing in it is signed
+    fn unsigned_message(
+        process_id_in: &str,
+        id: &str,
+        nonce_in: i32,
+        timestamp_in: i64,
+    ) -> Message {
+        let owner = serde_json::json!({ "address": "owner", "key": "key" });
+        serde_json::from_value(serde_json::json!({
+            "message": {
+                "id": id,
+                "owner": owner,
+                "data": null,
+                "tags": [],
+                "signature": "signature",
+                "anchor": null,
+                "target": null,
+            },
+            "assignment": {
+                "id": format!("assignment-{}", id),
+                "owner": owner,
+                "tags": [
+                    { "name": "Process", "value": process_id_in },
+                    { "name": "Epoch", "value": "0" },
+                    { "name": "Nonce", "value": nonce_in.to_string() },
+                    { "name": "Timestamp", "value": timestamp_in.to_string() },
+                    { "name": "Hash-Chain", "value": "hash-chain" },
+                ],
+                "signature": "signature",
+                "anchor": null,
+                "target": null,
+            },
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    #[ignore = "needs a postgres su database in DATABASE_URL"]
+    fn test_serializable_saves_race() {
+        let mut store = test_store();
+        store.run_migrations().unwrap();
+        store.serializable_saves = true;
+        let run = format!("race-{}", Utc::now().timestamp_nanos_opt().unwrap());
+        diesel::sql_query(
+            "INSERT INTO processes (process_id, process_data, bundle) VALUES ($1, '{}', '')",
+        )
+        .bind::<Text, _>(&run)
+        .execute(&mut store.get_conn().unwrap())
+        .unwrap();
+
+        // two writers on one nonce, exactly one of them gets it
+        let barrier = std::sync::Barrier::new(2);
+        let results: Vec<Result<SaveOutcome, StoreErrorType>> = std::thread::scope(|s| {
+            let writers: Vec<_> = ["a", "b"]
+                .iter()
+                .map(|writer| {
+                    let (store, barrier, run) = (&store, &barrier, &run);
+                    s.spawn(move || {
+                        let message = unsigned_message(run, &format!("{}-{}", run, writer), 1, 1);
+                        barrier.wait();
+                        store.save_message_blocking(&message, &[0])
+                    })
+                })
+                .collect();
+            writers.into_iter().map(|w| w.join().unwrap()).collect()
+        });
+        assert_eq!(results.iter().filter(|r| r.is_ok()).count(), 1);
+        for result in results {
+            match result {
+                Ok(SaveOutcome::Inserted { .. }) => (),
+                Err(StoreErrorType::NotAllowed(_)) | Err(StoreErrorType::MessageExists(_)) => (),
+                other => panic!("unexpected save result {:?}", other),
+            }
+        }
+
+        // writers racing through the same nonces never store one out of order
+        std::thread::scope(|s| {
+            for writer in 0..4 {
+                let (store, run) = (&store, &run);
+                s.spawn(move || {
+                    for nonce_in in 2..40 {
+                        let id = format!("{}-{}-{}", run, writer, nonce_in);
+                        let _ = store
+                            .save_message_blocking(&unsigned_message(run, &id, nonce_in, 1), &[0]);
+                    }
+                });
+            }
+        });
+        use super::super::schema::messages::dsl::*;
+        let stored: Vec<i32> = messages
+            .filter(process_id.eq(&run))
+            .order(row_id.asc())
+            .select(nonce)
+            .load(&mut store.get_conn().unwrap())
+            .unwrap();
+        assert!(stored.len() > 1);
+        assert!(
+            stored.windows(2).all(|w| w[0] < w[1]),
+            "nonces out of order: {:?}",
+            stored
+        );
+    }
+
+    #[test]
+    #[ignore = "needs a postgres su database in DATABASE_URL"]
+    fn test_next_cursor_pages_through_ties() {
+        let (store, process) = seeded_store("cursor-ties");
+        // pages of one and two end between messages sharing a timestamp
+        for limit in [1, 2] {
+            let mut from: Option<String> = None;
+            let mut seen = vec![];
+            loop {
+                let page = store
+                    .get_messages(
+                        &process,
+                        &from,
+                        &None,
+                        &Some(limit),
+                        false,
+                        MessageRange::default(),
+                    )
+                    .unwrap();
+                seen.extend(page.edges.iter().map(|edge| edge.node.timestamp().unwrap()));
+                match page.page_info.next_cursor {
+                    Some(cursor) => from = Some(cursor),
+                    None => break,
+                }
+            }
+            assert_eq!(seen, TIMESTAMPS.to_vec());
+        }
+    }
+
+    #[test]
+    fn test_parse_from_cursor() {
+        assert_eq!(parse_from_cursor("20").unwrap(), (20, None));
+        assert_eq!(parse_from_cursor("20:7").unwrap(), (20, Some(7)));
+        assert!(parse_from_cursor("20:").is_err());
+        assert!(parse_from_cursor("x:7").is_err());
+    }
+
+    #[test]
+    fn test_export_frames_read_back() {
+        for format in [ExportFormat::Ndjson, ExportFormat::LengthPrefixed] {
+            let mut written = vec![];
+            let exported = vec![
+                (
+                    "m1".to_string(),
+                    Some("a1".to_string()),
+                    "p".to_string(),
+                    10,
+                    vec![1, 2, 3],
+                ),
+                ("m2".to_string(), None, "p".to_string(), 20, vec![]),
+            ];
+            let mut bytes = 0;
+            for message in exported.clone() {
+                bytes += write_export_frame(&mut written, format, message).unwrap();
+            }
+            assert_eq!(bytes, written.len() as u64);
+
+            let mut reader = Cursor::new(written);
+            for (m_id, a_id, p_id, ts, bundle_in) in exported {
+                let (frame, read_bundle) = read_export_frame(&mut reader, format)
+                    .unwrap()
+                    .unwrap()
+                    .unwrap();
+                assert_eq!(frame.message_id, m_id);
+                assert_eq!(frame.assignment_id, a_id);
+                assert_eq!(frame.process_id, p_id);
+                assert_eq!(frame.timestamp, ts);
+                assert_eq!(read_bundle, bundle_in);
+            }
+            assert!(read_export_frame(&mut reader, format).unwrap().is_none());
+        }
+    }
+
+    #[test]
+    fn test_truncated_length_prefixed_frame_fails() {
+        let mut written = vec![];
+        let exported = ("m1".to_string(), None, "p".to_string(), 10, vec![1, 2, 3]);
+        write_export_frame(&mut written, ExportFormat::LengthPrefixed, exported).unwrap();
+        written.pop();
+
+        let mut reader = Cursor::new(written);
+        assert!(matches!(
+            read_export_frame(&mut reader, ExportFormat::LengthPrefixed),
+            Err(StoreErrorType::IoError(_))
+        ));
+    }
+
+    #[test]
+    fn test_bundle_codecs_round_trip() {
+        let repetitive = b"Data-Protocol ao ".repeat(64);
+        let compressed = compress_bundle(&repetitive).unwrap();
+        assert!(compressed.len() < repetitive.len());
+        assert_eq!(
+            decode_bundle(compressed, BUNDLE_CODEC_ZSTD).unwrap(),
+            repetitive
+        );
+
+        // too short to shrink, stays raw
+        assert!(compress_bundle(&[1, 2, 3]).is_none());
+        assert_eq!(
+            decode_bundle(vec![1, 2, 3], BUNDLE_CODEC_RAW).unwrap(),
+            vec![1, 2, 3]
+        );
+        assert!(decode_bundle(vec![1, 2, 3], BUNDLE_CODEC_ZSTD).is_err());
+        assert!(decode_bundle(vec![1, 2, 3], 7).is_err());
+    }
+
+    #[test]
+    #[ignore = "needs a postgres su database in DATABASE_URL"]
+    fn test_stitched_windows_cover_each_message_once() {
+        let seeded = seeded_store("stitched");
+        let half_open = MessageRange {
+            order: SortOrder::Asc,
+            from_inclusive: true,
+            to_inclusive: false,
+        };
+        assert_eq!(
+            stitched(&seeded, half_open, &[10, 20, 30, 41]),
+            TIMESTAMPS.to_vec()
+        );
+
+        let default = MessageRange::default();
+        assert_eq!(
+            stitched(&seeded, default, &[0, 20, 30, 40]),
+            TIMESTAMPS.to_vec()
+        );
+
+        let desc = MessageRange {
+            order: SortOrder::Desc,
+            ..MessageRange::default()
+        };
+        let mut newest_first = TIMESTAMPS.to_vec();
+        newest_first.reverse();
+        assert_eq!(stitched(&seeded, desc, &[50, 30, 20, 0]), newest_first);
+    }
+
+    #[test]
+    #[ignore = "needs a postgres su database in DATABASE_URL"]
+    fn test_rename_process_only_corrects_columns() {
+        let (store, process) = seeded_store("rename");
+        let conn = &mut store.get_conn().unwrap();
+
+        // the payloads still name the process but the columns went in wrong
+        let wrong = format!("{}-wrong", process);
+        diesel::sql_query(
+            "INSERT INTO processes (process_id, process_data, bundle) \
+             VALUES ($1, jsonb_build_object('process_id', $2::text), '')",
+        )
+        .bind::<Text, _>(&wrong)
+        .bind::<Text, _>(&process)
+        .execute(conn)
+        .unwrap();
+        diesel::sql_query("UPDATE messages SET process_id = $1 WHERE process_id = $2")
+            .bind::<Text, _>(&wrong)
+            .bind::<Text, _>(&process)
+            .execute(conn)
+            .unwrap();
+
+        // new_id is still taken by the old processes row
+        assert!(matches!(
+            store.rename_process(&wrong, &process),
+            Err(StoreErrorType::NotAllowed(_))
+        ));
+        diesel::sql_query("DELETE FROM processes WHERE process_id = $1")
+            .bind::<Text, _>(&process)
+            .execute(conn)
+            .unwrap();
+
+        // the payloads don't name this id, so nothing moves
+        let elsewhere = format!("{}-elsewhere", process);
+        assert!(matches!(
+            store.rename_process(&wrong, &elsewhere),
+            Err(StoreErrorType::NotAllowed(_))
+        ));
+
+        let report = store.rename_process(&wrong, &process).unwrap();
+        assert_eq!(report.processes_renamed, 1);
+        assert_eq!(report.messages_renamed, TIMESTAMPS.len());
+        let db_messages = super::super::schema::messages::dsl::messages
+            .filter(super::super::schema::messages::dsl::process_id.eq(&process))
+            .load::<DbMessage>(conn)
+            .unwrap();
+        let renamed: Vec<String> = store
+            .db_messages_to_messages(conn, &db_messages)
+            .unwrap()
+            .iter()
+            .map(|m| m.process_id().unwrap())
+            .collect();
+        assert_eq!(renamed, vec![process.clone(); TIMESTAMPS.len()]);
+    }
+
+    #[test]
+    #[ignore = "needs a postgres su database in DATABASE_URL"]
+    fn test_delete_process() {
+        use super::super::schema::messages::dsl as messages_dsl;
+        use super::super::schema::process_schedulers::dsl as process_schedulers_dsl;
+        use super::super::schema::processes::dsl as processes_dsl;
+        use super::super::schema::schedulers::dsl as schedulers_dsl;
+
+        let (mut store, process) = seeded_store("delete");
+        let conn = &mut store.get_conn().unwrap();
+        let scheduler = scheduled_on(&store, &process, &format!("https://{}", process));
+
+        assert!(matches!(
+            store.delete_process(&process),
+            Err(StoreErrorType::NotAllowed(_))
+        ));
+        store.enable_process_deletion = true;
+
+        let report = store.delete_process(&process).unwrap();
+        assert_eq!(report.processes_deleted, 1);
+        assert_eq!(report.messages_deleted, TIMESTAMPS.len());
+        assert_eq!(report.process_schedulers_deleted, 1);
+        let left: i64 = messages_dsl::messages
+            .filter(messages_dsl::process_id.eq(&process))
+            .count()
+            .get_result(conn)
+            .unwrap();
+        assert_eq!(left, 0);
+        let left: i64 = processes_dsl::processes
+            .filter(processes_dsl::process_id.eq(&process))
+            .count()
+            .get_result(conn)
+            .unwrap();
+        assert_eq!(left, 0);
+        let left: i64 = process_schedulers_dsl::process_schedulers
+            .filter(process_schedulers_dsl::process_id.eq(&process))
+            .count()
+            .get_result(conn)
+            .unwrap();
+        assert_eq!(left, 0);
+        let process_count: i32 = schedulers_dsl::schedulers
+            .filter(schedulers_dsl::row_id.eq(scheduler.row_id.unwrap()))
+            .select(schedulers_dsl::process_count)
+            .first(conn)
+            .unwrap();
+        assert_eq!(process_count, 0);
+    }
+
+    #[test]
+    #[ignore = "needs a postgres su database in DATABASE_URL"]
+    fn test_delete_unknown_process_removes_nothing() {
+        use super::super::schema::process_activity::dsl as process_activity_dsl;
+
+        let mut store = test_store();
+        store.run_migrations().unwrap();
+        store.enable_process_deletion = true;
+        let conn = &mut store.get_conn().unwrap();
+        let unknown = format!("unknown-{}", Utc::now().timestamp_nanos_opt().unwrap());
+        touch_process_activity(conn, &unknown, 10).unwrap();
+
+        match store.delete_process(&unknown) {
+            Err(StoreErrorType::NotFound(Entity::Process, id)) => assert_eq!(id, unknown),
+            other => panic!("expected NotFound, got {:?}", other),
+        }
+        // the stray activity row was rolled back with the rest
+        let left: i64 = process_activity_dsl::process_activity
+            .filter(process_activity_dsl::process_id.eq(&unknown))
+            .count()
+            .get_result(conn)
+            .unwrap();
+        assert_eq!(left, 1);
+    }
+
+    #[test]
+    #[ignore = "needs a postgres su database in DATABASE_URL"]
+    fn test_repeated_save_matches_across_isolation() {
+        let mut store = test_store();
+        store.run_migrations().unwrap();
+        let (_, process) = seeded_store("repeat");
+
+        for serializable in [false, true] {
+            store.serializable_saves = serializable;
+            let id = format!("{}-{}", process, serializable);
+            let nonce_in = if serializable { 12 } else { 10 };
+
+            // an assignment alone is a repeat, not a nonce out of order
+            let mut assignment = unsigned_message(&process, &id, nonce_in, 50);
+            assignment.message = None;
+            assignment.assignment.tags.push(Tag::new("Message", &id));
+            let row_id_in = match store.save_message_blocking(&assignment, &[0]) {
+                Ok(SaveOutcome::Inserted { row_id }) => row_id,
+                other => panic!("unexpected first save {:?}", other),
+            };
+            match store.save_message_blocking(&assignment, &[0]) {
+                Ok(SaveOutcome::AlreadyExists { row_id }) => assert_eq!(row_id, row_id_in),
+                other => panic!("unexpected repeated save {:?}", other),
+            }
+
+            // a data item can only be written once
+            let message = unsigned_message(&process, &format!("{}-item", id), nonce_in + 1, 50);
+            assert!(store.save_message_blocking(&message, &[0]).is_ok());
+            assert!(matches!(
+                store.save_message_blocking(&message, &[0]),
+                Err(StoreErrorType::MessageExists(_))
+            ));
+        }
+    }
+
+    // EXPLAIN in front of a query, keeping its binds
+    #[derive(QueryId)]
+    struct Explain<Q>(Q);
+
+    impl<Q: QueryFragment<Pg>> QueryFragment<Pg> for Explain<Q> {
+        fn walk_ast<'b>(&'b self, mut out: AstPass<'_, 'b, Pg>) -> QueryResult<()> {
+            out.push_sql("EXPLAIN ");
+            self.0.walk_ast(out.reborrow())
+        }
+    }
+
+    impl<Q> Query for Explain<Q> {
+        type SqlType = Text;
+    }
+
+    impl<Q> RunQueryDsl<PgConnection> for Explain<Q> {}
+
+    #[test]
+    #[ignore = "needs a postgres su database in DATABASE_URL"]
+    fn test_message_page_is_index_only() {
+        let (store, process) = seeded_store("explain");
+        let conn = &mut store.get_conn().unwrap();
+        diesel::sql_query(
+            "INSERT INTO messages \
+             (process_id, message_id, epoch, nonce, timestamp, bundle, hash_chain, bundle_size) \
+             SELECT $1, $1 || '-bulk-' || n, 0, n, n, '\\x00', 'hash-chain', 1 \
+             FROM generate_series(100, 20100) n",
+        )
+        .bind::<Text, _>(&process)
+        .execute(conn)
+        .unwrap();
+        // index only scans need the visibility map a vacuum leaves behind
+        diesel::sql_query("VACUUM ANALYZE messages")
+            .execute(conn)
+            .unwrap();
+
+        let desc = MessageRange {
+            order: SortOrder::Desc,
+            ..MessageRange::default()
+        };
+        for (from_cursor, range) in [
+            (None, MessageRange::default()),
+            (Some((5000, Some(1))), MessageRange::default()),
+            (Some((5000, None)), desc),
+        ] {
+            let plan: Vec<String> = Explain(messages_page_keys(
+                &process,
+                from_cursor,
+                Some(15000),
+                range,
+                101,
+            ))
+            .load(conn)
+            .unwrap();
+            let plan = plan.join("\n");
+            assert!(
+                plan.contains("Index Only Scan")
+                    && plan.contains("idx_messages_process_id_timestamp_row_id"),
+                "{}",
+                plan
+            );
+            assert!(!plan.contains("Sort"), "{}", plan);
+        }
+    }
+
+    #[test]
+    #[ignore = "needs a postgres su database in DATABASE_URL"]
+    fn test_batch_skips_stored_messages_and_data_items() {
+        let (store, process) = seeded_store("batch-skip");
+        let seeded: Vec<Message> = TIMESTAMPS
+            .iter()
+            .enumerate()
+            .map(|(i, t)| unsigned_message(&process, &format!("{}-{}", process, i), i as i32, *t))
+            .collect();
+
+        // a rerun inserts nothing and every message is found at its key
+        let rerun: Vec<(Message, Vec<u8>)> = seeded.iter().map(|m| (m.clone(), vec![0])).collect();
+        let (report, _) = store.save_messages_batch_indexed(&rerun);
+        assert_eq!((report.saved, report.skipped), (0, TIMESTAMPS.len()));
+        let seeded_refs: Vec<&Message> = seeded.iter().collect();
+        assert_eq!(
+            store.stored_at_same_key(&seeded_refs).unwrap(),
+            vec![true; TIMESTAMPS.len()]
+        );
+
+        // a stored data item on a new nonce, and a new one twice in the chunk
+        let moved = unsigned_message(&process, &format!("{}-0", process), 10, 50);
+        let fresh = unsigned_message(&process, &format!("{}-fresh", process), 11, 50);
+        let fresh_again = unsigned_message(&process, &format!("{}-fresh", process), 12, 50);
+        let batch = vec![
+            (moved.clone(), vec![0]),
+            (fresh.clone(), vec![0]),
+            (fresh_again.clone(), vec![0]),
+        ];
+        let (report, inserted) = store.save_messages_batch_indexed(&batch);
+        assert_eq!((report.saved, report.skipped), (1, 2));
+        assert_eq!(inserted, vec![1]);
+        assert_eq!(
+            store
+                .stored_at_same_key(&[&moved, &fresh, &fresh_again])
+                .unwrap(),
+            vec![false, true, false]
+        );
+    }
+
+    #[test]
+    #[ignore = "needs a postgres su database in DATABASE_URL"]
+    fn test_process_scheduler_counted_once() {
+        use super::super::schema::schedulers::dsl as schedulers_dsl;
+
+        let (store, process) = seeded_store("counted");
+        let url = format!("https://{}", process);
+        let scheduler = scheduled_on(&store, &process, &url);
+        let process_count = || -> i32 {
+            schedulers_dsl::schedulers
+                .filter(schedulers_dsl::row_id.eq(scheduler.row_id.unwrap()))
+                .select(schedulers_dsl::process_count)
+                .first(&mut store.get_conn().unwrap())
+                .unwrap()
+        };
+        assert_eq!(process_count(), 1);
+
+        // a retried spawn maps nothing new and counts nothing
+        let retried = ProcessScheduler {
+            row_id: None,
+            process_id: process.clone(),
+            scheduler_row_id: scheduler.row_id.unwrap(),
+        };
+        assert!(!store.save_process_scheduler(&retried).unwrap());
+        assert_eq!(process_count(), 1);
+    }
+
+    #[test]
+    #[ignore = "needs a postgres su database in DATABASE_URL"]
+    fn test_save_needs_one_connection() {
+        let (store, process) = seeded_store("one-conn");
+
+        // every connection but one is taken, as by busy ingest workers
+        let held: Vec<_> = (1..POOL_MAX_SIZE)
+            .map(|_| store.get_conn().unwrap())
+            .collect();
+        let message = unsigned_message(&process, &format!("{}-late", process), 10, 50);
+        assert!(matches!(
+            store.save_message_blocking(&message, &[0]),
+            Ok(SaveOutcome::Inserted { .. })
+        ));
+        assert!(matches!(
+            store.save_message_blocking(&message, &[0]),
+            Err(StoreErrorType::MessageExists(_))
+        ));
+        drop(held);
+    }
+
+    #[test]
+    #[ignore = "needs a postgres su database in DATABASE_URL"]
+    fn test_message_rate_buckets() {
+        let (store, process) = seeded_store("rate");
+        let late = unsigned_message(&process, &format!("{}-late", process), 6, 2500);
+        store.save_message_blocking(&late, &[0]).unwrap();
+
+        assert_eq!(
+            store.get_message_rate(&process, 1, 0, 10_000).unwrap(),
+            vec![(0, 6), (2000, 1)]
+        );
+        // from is inclusive, to is not
+        assert_eq!(
+            store.get_message_rate(&process, 1, 20, 40).unwrap(),
+            vec![(0, 3)]
+        );
+        assert!(store
+            .get_message_rate(&process, 1, 40, 40)
+            .unwrap()
+            .is_empty());
+        assert!(store
+            .get_message_rate(&process, 1, 50, 10)
+            .unwrap()
+            .is_empty());
+        assert!(store
+            .get_message_rate(&format!("{}-unknown", process), 1, 0, 10_000)
+            .unwrap()
+            .is_empty());
+
+        for bucket_seconds in [0, -1, i64::MAX] {
+            assert!(matches!(
+                store.get_message_rate(&process, bucket_seconds, 0, 10_000),
+                Err(StoreErrorType::InvalidRange(_))
+            ));
+        }
+    }
+
+    #[test]
+    #[ignore = "needs a postgres su database in DATABASE_URL"]
+    fn test_reindex_process_rebuilds_derived_rows() {
+        use super::super::schema::message_tags::dsl as message_tags_dsl;
+        use super::super::schema::messages::dsl as messages_dsl;
+        use super::super::schema::process_activity::dsl as process_activity_dsl;
+
+        let (store, process) = seeded_store("reindex");
+        let mut tagged = unsigned_message(&process, &format!("{}-tagged", process), 6, 50);
+        tagged
+            .message
+            .as_mut()
+            .unwrap()
+            .tags
+            .push(Tag::new("Action", "Transfer"));
+        store.save_message_blocking(&tagged, &[0, 0, 0]).unwrap();
+        let conn = &mut store.get_conn().unwrap();
+        let row_ids: Vec<i32> = messages_dsl::messages
+            .filter(messages_dsl::process_id.eq(&process))
+            .select(messages_dsl::row_id)
+            .load(conn)
+            .unwrap();
+        let derived = |conn: &mut PgConnection| -> (Vec<(String, String)>, Vec<i32>, Option<i64>) {
+            let tags = message_tags_dsl::message_tags
+                .filter(message_tags_dsl::message_row_id.eq_any(&row_ids))
+                .select((message_tags_dsl::name, message_tags_dsl::value))
+                .load(conn)
+                .unwrap();
+            let sizes = messages_dsl::messages
+                .filter(messages_dsl::process_id.eq(&process))
+                .order(messages_dsl::row_id.asc())
+                .select(messages_dsl::bundle_size)
+                .load(conn)
+                .unwrap();
+            let last = process_activity_dsl::process_activity
+                .filter(process_activity_dsl::process_id.eq(&process))
+                .select(process_activity_dsl::last_timestamp)
+                .first(conn)
+                .optional()
+                .unwrap();
+            (tags, sizes, last)
+        };
+        let saved = derived(conn);
+        assert_eq!(
+            saved.0,
+            vec![("Action".to_string(), "Transfer".to_string())]
+        );
+        assert_eq!(saved.2, Some(50));
+
+        diesel::delete(
+            message_tags_dsl::message_tags
+                .filter(message_tags_dsl::message_row_id.eq_any(&row_ids)),
+        )
+        .execute(conn)
+        .unwrap();
+        diesel::update(messages_dsl::messages.filter(messages_dsl::process_id.eq(&process)))
+            .set(messages_dsl::bundle_size.eq(0))
+            .execute(conn)
+            .unwrap();
+        touch_process_activity(conn, &process, 10_000).unwrap();
+
+        // run twice, the second pass replaces rather than adds
+        for _ in 0..2 {
+            assert_eq!(
+                store.reindex_process(&process).unwrap(),
+                TIMESTAMPS.len() + 1
+            );
+            assert_eq!(derived(conn), saved);
+        }
+    }
+
+    #[test]
+    #[ignore = "needs a postgres su database in DATABASE_URL"]
+    fn test_reindex_unknown_process() {
+        use super::super::schema::process_activity::dsl as process_activity_dsl;
+
+        let store = test_store();
+        store.run_migrations().unwrap();
+        let conn = &mut store.get_conn().unwrap();
+        let unknown = format!("unknown-{}", Utc::now().timestamp_nanos_opt().unwrap());
+        touch_process_activity(conn, &unknown, 10).unwrap();
+
+        assert_eq!(store.reindex_process(&unknown).unwrap(), 0);
+        // with no messages there is no activity to keep
+        let left: i64 = process_activity_dsl::process_activity
+            .filter(process_activity_dsl::process_id.eq(&unknown))
+            .count()
+            .get_result(conn)
+            .unwrap();
+        assert_eq!(left, 0);
+    }
+
+    #[test]
+    #[ignore = "needs a postgres su database in DATABASE_URL"]
+    fn test_reassign_then_delete_scheduler() {
+        let (store, process) = seeded_store("reassign");
+        let old = scheduled_on(&store, &process, &format!("https://old.{}", process));
+        let new_url = format!("https://new.{}", process);
+        store
+            .save_scheduler(&Scheduler {
+                row_id: None,
+                url: new_url.clone(),
+                process_count: 0,
+            })
+            .unwrap();
+        let new = store.get_scheduler_by_url(&new_url).unwrap();
+        let (old_row_id, new_row_id) = (old.row_id.unwrap(), new.row_id.unwrap());
+        let counts = || {
+            (
+                store.get_scheduler(&old_row_id).unwrap().process_count,
+                store.get_scheduler(&new_row_id).unwrap().process_count,
+            )
+        };
+
+        // still referenced by the process
+        assert!(matches!(
+            store.delete_scheduler(old_row_id),
+            Err(StoreErrorType::NotAllowed(_))
+        ));
+        assert!(store.get_scheduler(&old_row_id).is_ok());
+
+        // a missing target rolls back the whole move
+        assert!(matches!(
+            store.reassign_process(&process, i32::MAX),
+            Err(StoreErrorType::NotFound(Entity::Scheduler, _))
+        ));
+        assert_eq!(counts(), (1, 0));
+        assert_eq!(
+            store
+                .get_process_scheduler(&process)
+                .unwrap()
+                .scheduler_row_id,
+            old_row_id
+        );
+
+        assert!(matches!(
+            store.reassign_process(&format!("{}-unknown", process), new_row_id),
+            Err(StoreErrorType::NotFound(Entity::ProcessScheduler, _))
+        ));
+        assert_eq!(counts(), (1, 0));
+
+        assert_eq!(
+            store.reassign_process(&process, old_row_id).unwrap(),
+            "unchanged"
+        );
+        assert_eq!(counts(), (1, 0));
+
+        assert_eq!(
+            store.reassign_process(&process, new_row_id).unwrap(),
+            "reassigned"
+        );
+        assert_eq!(counts(), (0, 1));
+        assert_eq!(
+            store
+                .get_process_scheduler(&process)
+                .unwrap()
+                .scheduler_row_id,
+            new_row_id
+        );
+
+        assert_eq!(store.delete_scheduler(old_row_id).unwrap(), "deleted");
+        assert!(store.get_scheduler(&old_row_id).is_err());
+        assert!(matches!(
+            store.delete_scheduler(old_row_id),
+            Err(StoreErrorType::NotFound(Entity::Scheduler, _))
+        ));
+    }
+
+    #[test]
+    #[ignore = "needs a postgres su database in DATABASE_URL"]
+    fn test_list_processes_pages_by_row_id() {
+        use super::super::schema::processes::dsl as processes_dsl;
+
+        let (store, process) = seeded_store("list");
+        let ours = vec![
+            process.clone(),
+            format!("{}-b", process),
+            format!("{}-c", process),
+        ];
+        for process_id_in in ours.iter().skip(1) {
+            diesel::sql_query(
+                "INSERT INTO processes (process_id, process_data, bundle) VALUES ($1, '{}', '')",
+            )
+            .bind::<Text, _>(process_id_in)
+            .execute(&mut store.get_conn().unwrap())
+            .unwrap();
+        }
+        let first: i32 = processes_dsl::processes
+            .filter(processes_dsl::process_id.eq(&process))
+            .select(processes_dsl::row_id)
+            .first(&mut store.get_conn().unwrap())
+            .unwrap();
+
+        // other tests insert alongside, so keep only ours from each page
+        let mut listed = vec![];
+        let mut after = Some(first - 1);
+        loop {
+            let page = store.list_processes(after, 2).unwrap();
+            assert!(page.len() <= 2);
+            assert!(page.windows(2).all(|w| w[0].0 < w[1].0));
+            assert!(page.iter().all(|(row_id, _)| Some(*row_id) > after));
+            listed.extend(
+                page.iter()
+                    .map(|(_, id)| id.clone())
+                    .filter(|id| id.starts_with(&process)),
+            );
+            match page.last() {
+                Some((row_id, _)) if page.len() == 2 => after = Some(*row_id),
+                _ => break,
+            }
+        }
+        assert_eq!(listed, ours);
+
+        assert!(store.list_processes(Some(i32::MAX), 10).unwrap().is_empty());
+        assert!(store.list_processes(None, 0).unwrap().is_empty());
+    }
+
+    #[test]
+    #[ignore = "needs a postgres su database in DATABASE_URL"]
+    fn test_compress_stored_bundles() {
+        use super::super::schema::messages::dsl as messages_dsl;
+
+        let (store, process) = seeded_store("compress");
+        let large_id = format!("{}-large", process);
+        let large = vec![7u8; 4096];
+        store
+            .save_message_blocking(&unsigned_message(&process, &large_id, 6, 50), &large)
+            .unwrap();
+        let codecs = || -> Vec<(String, i16)> {
+            messages_dsl::messages
+                .filter(messages_dsl::process_id.eq(&process))
+                .order(messages_dsl::row_id.asc())
+                .select((messages_dsl::message_id, messages_dsl::bundle_codec))
+                .load(&mut store.get_conn().unwrap())
+                .unwrap()
+        };
+        let first: i32 = messages_dsl::messages
+            .filter(messages_dsl::process_id.eq(&process))
+            .select(diesel::dsl::min(messages_dsl::row_id))
+            .first::<Option<i32>>(&mut store.get_conn().unwrap())
+            .unwrap()
+            .unwrap();
+        assert!(codecs().iter().all(|(_, c)| *c == BUNDLE_CODEC_RAW));
+
+        let mut after = first - 1;
+        let mut looked_at = 0;
+        loop {
+            let (count, next) = store.compress_stored_bundles(after, 3).unwrap();
+            looked_at += count;
+            match next {
+                Some(next) => after = next,
+                None => break,
+            }
+        }
+        // the seeded messages and the large one, plus whatever other tests saved
+        assert!(looked_at > TIMESTAMPS.len());
+
+        // a single byte can't shrink, so only the large bundle changes
+        for (id, codec) in codecs() {
+            match id == large_id {
+                true => assert_eq!(codec, BUNDLE_CODEC_ZSTD),
+                false => assert_eq!(codec, BUNDLE_CODEC_RAW),
+            }
+        }
+        assert_eq!(store.get_bundle_bytes(&large_id).unwrap(), large);
+        assert_eq!(
+            store.get_bundle_bytes(&format!("{}-0", process)).unwrap(),
+            vec![0]
+        );
+
+        assert_eq!(
+            store.compress_stored_bundles(i32::MAX, 10).unwrap(),
+            (0, None)
+        );
+    }
+
+    #[test]
+    #[ignore = "needs a postgres su database in DATABASE_URL"]
+    fn test_messages_by_tag() {
+        let (store, process) = seeded_store("by-tag");
+        let (other_store, other) = seeded_store("by-tag-other");
+        let tagged = |store: &StoreClient, process_id_in: &str, nonce_in: i32, action: &str| {
+            let id = format!("{}-{}-{}", process_id_in, action.to_lowercase(), nonce_in);
+            let mut message = unsigned_message(process_id_in, &id, nonce_in, 50);
+            message
+                .message
+                .as_mut()
+                .unwrap()
+                .tags
+                .push(Tag::new("Action", action));
+            store.save_message_blocking(&message, &[0]).unwrap();
+        };
+        tagged(&store, &process, 6, "Transfer");
+        tagged(&store, &process, 7, "Eval");
+        tagged(&store, &process, 8, "Transfer");
+        tagged(&other_store, &other, 6, "Transfer");
+
+        let ids = |tag_value: &str, limit: i64| -> Vec<String> {
+            store
+                .get_messages_by_tag(&process, "Action", tag_value, limit)
+                .unwrap()
+                .into_iter()
+                .map(|m| m.message.unwrap().id)
+                .collect()
+        };
+        let transfer = |nonce_in: i32| format!("{}-transfer-{}", process, nonce_in);
+        // only this process's, in the order they were scheduled
+        assert_eq!(ids("Transfer", 10), vec![transfer(6), transfer(8)]);
+        assert_eq!(ids("Transfer", 1), vec![transfer(6)]);
+        assert_eq!(ids("Eval", 10), vec![format!("{}-eval-7", process)]);
+        assert!(ids("Burn", 10).is_empty());
+        assert!(store
+            .get_messages_by_tag(&format!("{}-unknown", process), "Action", "Transfer", 10)
+            .unwrap()
+            .is_empty());
+    }
+}
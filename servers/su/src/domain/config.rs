@@ -1,6 +1,9 @@
 use std::env;
+use std::fmt;
+use std::path::Path;
 
 use dotenv::dotenv;
+use reqwest::Url;
 
 use crate::domain::Config;
 
@@ -12,26 +15,165 @@ pub struct AoConfig {
     pub upload_node_url: String,
     pub mode: String,
     pub scheduler_list_path: String,
+    pub enable_process_deletion: bool,
+    pub db_warmup_connections: usize,
+    pub db_min_idle: Option<u32>,
+    pub pool_stats_interval_secs: u64,
+    pub migrations_dir: Option<String>,
+    pub db_schema: Option<String>,
+    pub maintenance_interval_secs: u64,
+    pub maintenance_vacuum: bool,
+    pub statement_timeout_ms: u64,
+    pub db_max_lifetime_secs: u64,
+    pub db_idle_timeout_secs: u64,
+    pub db_connection_timeout_secs: u64,
+    pub omit_message_data: bool,
+    pub serializable_saves: bool,
+    pub slow_acquire_ms: u64,
+    pub insert_chunk_size: usize,
+    pub bundle_table: bool,
+    pub compress_bundles: bool,
+    pub ingest_workers: usize,
+    pub ingest_queue_depth: usize,
 }
 
 impl AoConfig {
-    pub fn new(mode: Option<String>) -> Result<Self, env::VarError> {
+    pub fn new(mode: Option<String>) -> Result<Self, String> {
         dotenv().ok();
         let mode_out = match mode {
             Some(m) => m,
-            None => env::var("MODE")?,
+            None => env_var("MODE")?,
         };
         Ok(AoConfig {
-            database_url: env::var("DATABASE_URL")?,
-            su_wallet_path: env::var("SU_WALLET_PATH")?,
-            gateway_url: env::var("GATEWAY_URL")?,
-            upload_node_url: env::var("UPLOAD_NODE_URL")?,
+            database_url: env_var("DATABASE_URL")?,
+            su_wallet_path: env_var("SU_WALLET_PATH")?,
+            gateway_url: env_var("GATEWAY_URL")?,
+            upload_node_url: env_var("UPLOAD_NODE_URL")?,
             mode: mode_out,
-            scheduler_list_path: env::var("SCHEDULER_LIST_PATH")?,
+            scheduler_list_path: env_var("SCHEDULER_LIST_PATH")?,
+            enable_process_deletion: env_flag("ENABLE_PROCESS_DELETION"),
+            db_warmup_connections: env_number("DB_WARMUP_CONNECTIONS", 0)?,
+            db_min_idle: match env::var("DB_MIN_IDLE") {
+                Ok(_) => Some(env_number("DB_MIN_IDLE", 0)?),
+                Err(_) => None,
+            },
+            pool_stats_interval_secs: env_number("POOL_STATS_INTERVAL_SECS", 0)?,
+            migrations_dir: env::var("MIGRATIONS_DIR").ok(),
+            db_schema: env::var("DB_SCHEMA").ok(),
+            maintenance_interval_secs: env_number("MAINTENANCE_INTERVAL_SECS", 0)?,
+            maintenance_vacuum: env_flag("MAINTENANCE_VACUUM"),
+            statement_timeout_ms: env_number("STATEMENT_TIMEOUT_MS", 0)?,
+            db_max_lifetime_secs: env_number("DB_MAX_LIFETIME_SECS", 0)?,
+            db_idle_timeout_secs: env_number("DB_IDLE_TIMEOUT_SECS", 0)?,
+            db_connection_timeout_secs: env_number("DB_CONNECTION_TIMEOUT_SECS", 0)?,
+            omit_message_data: env_flag("OMIT_MESSAGE_DATA"),
+            serializable_saves: env_flag("SERIALIZABLE_SAVES"),
+            slow_acquire_ms: env_number("SLOW_ACQUIRE_MS", 0)?,
+            insert_chunk_size: env_number("INSERT_CHUNK_SIZE", 1000)?,
+            bundle_table: env_flag("BUNDLE_TABLE"),
+            compress_bundles: env_flag("COMPRESS_BUNDLES"),
+            ingest_workers: env_number("INGEST_WORKERS", 0)?,
+            ingest_queue_depth: env_number("INGEST_QUEUE_DEPTH", 100)?,
         })
     }
 }
 
+#[derive(Debug)]
+pub enum ConfigError {
+    InvalidUrl(String),
+    MissingPath(String),
+    OutOfRange(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::InvalidUrl(m) => write!(f, "invalid url, {}", m),
+            ConfigError::MissingPath(m) => write!(f, "path does not exist, {}", m),
+            ConfigError::OutOfRange(m) => write!(f, "value out of range, {}", m),
+        }
+    }
+}
+
+impl AoConfig {
+    /*
+        checks the settings new() can't catch while parsing
+        so a bad deploy fails at startup and not on the
+        first request that happens to use the setting
+    */
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        match Url::parse(&self.database_url) {
+            Ok(url) if url.scheme() == "postgres" || url.scheme() == "postgresql" => (),
+            Ok(url) => {
+                return Err(ConfigError::InvalidUrl(format!(
+                    "DATABASE_URL: expected a postgres url, got scheme {}",
+                    url.scheme()
+                )))
+            }
+            Err(e) => return Err(ConfigError::InvalidUrl(format!("DATABASE_URL: {}", e))),
+        }
+
+        for (name, value) in [
+            ("GATEWAY_URL", &self.gateway_url),
+            ("UPLOAD_NODE_URL", &self.upload_node_url),
+        ] {
+            Url::parse(value)
+                .map_err(|e| ConfigError::InvalidUrl(format!("{}: {} {}", name, e, value)))?;
+        }
+
+        if let Some(dir) = &self.migrations_dir {
+            if !Path::new(dir).is_dir() {
+                return Err(ConfigError::MissingPath(format!("MIGRATIONS_DIR: {}", dir)));
+            }
+        }
+
+        if let Some(schema) = &self.db_schema {
+            if schema.is_empty() || schema.len() > 63 {
+                return Err(ConfigError::OutOfRange(format!(
+                    "DB_SCHEMA: must be 1 to 63 characters, got {:?}",
+                    schema
+                )));
+            }
+        }
+
+        if self.insert_chunk_size == 0 {
+            return Err(ConfigError::OutOfRange(
+                "INSERT_CHUNK_SIZE: must be at least 1".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+fn env_var(name: &str) -> Result<String, String> {
+    env::var(name).map_err(|e| format!("{}: {}", name, e))
+}
+
+/*
+    optional boolean settings, anything other
+    than "true" leaves the setting off
+*/
+fn env_flag(name: &str) -> bool {
+    match env::var(name) {
+        Ok(val) => val == "true",
+        Err(_) => false,
+    }
+}
+
+/*
+    optional numeric settings, falls back to the
+    default when unset but fails on a bad value
+*/
+fn env_number<T: std::str::FromStr>(name: &str, default: T) -> Result<T, String> {
+    match env::var(name) {
+        Ok(val) => val
+            .parse::<T>()
+            .map_err(|_| format!("{}: invalid number {}", name, val)),
+        Err(_) => Ok(default),
+    }
+}
+
 impl Config for AoConfig {
     fn su_wallet_path(&self) -> String {
         self.su_wallet_path.clone()
@@ -66,6 +66,66 @@ impl DataBundle {
 
         Ok(buffer)
     }
+
+    /*
+        inverse of to_bytes, the tags are not part of the
+        ANS-104 binary so they are passed in separately
+    */
+    pub fn from_bytes(buffer: &[u8], tags: Vec<Tag>) -> Result<Self, ByteErrorType> {
+        if buffer.len() < 32 {
+            return Err(ByteErrorType::ByteError(
+                "Buffer too short for item count".to_string(),
+            ));
+        }
+        let item_count = byte_array_to_long(&buffer[0..32])? as usize;
+
+        // checked before any arithmetic so a huge count can't overflow the offsets
+        if item_count > (buffer.len() - 32) / 64 {
+            return Err(ByteErrorType::ByteError(
+                "Buffer too short for item headers".to_string(),
+            ));
+        }
+
+        let mut offset = 32 + 64 * item_count;
+        let mut data_bundle = DataBundle::new(tags);
+        for index in 0..item_count {
+            let header_start = 32 + 64 * index;
+            let item_size = byte_array_to_long(&buffer[header_start..header_start + 32])? as usize;
+            let end = offset
+                .checked_add(item_size)
+                .filter(|end| *end <= buffer.len())
+                .ok_or_else(|| ByteErrorType::ByteError("Buffer too short for item".to_string()))?;
+            let item = DataItem::from_bytes(buffer[offset..end].to_vec())?;
+            data_bundle.add_item(item);
+            offset = end;
+        }
+
+        Ok(data_bundle)
+    }
+}
+
+/*
+    bounds checked slice of untrusted input, the
+    offsets come from the buffer itself so an
+    overflow or a short buffer is an error not a panic
+*/
+fn slice_at(buffer: &[u8], start: usize, len: usize) -> Result<&[u8], ByteErrorType> {
+    start
+        .checked_add(len)
+        .and_then(|end| buffer.get(start..end))
+        .ok_or_else(|| ByteErrorType::ByteError("Buffer too short".to_string()))
+}
+
+fn byte_array_to_long(bytes: &[u8]) -> Result<u64, ByteErrorType> {
+    let (low, high) = bytes.split_at(8.min(bytes.len()));
+    if high.iter().any(|b| *b != 0) {
+        return Err(ByteErrorType::ByteError("value too large".to_string()));
+    }
+    let mut value = 0u64;
+    for (index, byte) in low.iter().enumerate() {
+        value |= (*byte as u64) << (8 * index);
+    }
+    Ok(value)
 }
 
 fn long_to_n_byte_array(n: usize, long: u64) -> Result<Vec<u8>, ByteErrorType> {
@@ -330,24 +390,24 @@ impl DataItem {
 
         let target_start = 2 + sig_length + pub_length;
         let target_present = u8::from_le_bytes(
-            <[u8; 1]>::try_from(&buffer[target_start..target_start + 1]).map_err(|err| {
+            <[u8; 1]>::try_from(slice_at(buffer, target_start, 1)?).map_err(|err| {
                 ByteErrorType::ByteError(format!("target bytes error - {}", err.to_string()))
             })?,
         );
         let target = match target_present {
             0 => &[],
-            1 => &buffer[target_start + 1..target_start + 33],
+            1 => slice_at(buffer, target_start + 1, 32)?,
             _b => return Err(ByteErrorType::ByteError("target bytes error".to_string())),
         };
         let anchor_start = target_start + 1 + target.len();
         let anchor_present = u8::from_le_bytes(
-            <[u8; 1]>::try_from(&buffer[anchor_start..anchor_start + 1]).map_err(|err| {
+            <[u8; 1]>::try_from(slice_at(buffer, anchor_start, 1)?).map_err(|err| {
                 ByteErrorType::ByteError(format!("anchor bytes error - {}", err.to_string()))
             })?,
         );
         let anchor = match anchor_present {
             0 => &[],
-            1 => &buffer[anchor_start + 1..anchor_start + 33],
+            1 => slice_at(buffer, anchor_start + 1, 32)?,
             b => {
                 return Err(ByteErrorType::ByteError(format!(
                     "anchor bytes error - {}",
@@ -358,20 +418,21 @@ impl DataItem {
 
         let tags_start = anchor_start + 1 + anchor.len();
         let number_of_tags = u64::from_le_bytes(
-            <[u8; 8]>::try_from(&buffer[tags_start..tags_start + 8]).map_err(|err| {
+            <[u8; 8]>::try_from(slice_at(buffer, tags_start, 8)?).map_err(|err| {
                 ByteErrorType::ByteError(format!("tag bytes error - {}", err.to_string()))
             })?,
         );
 
         let number_of_tags_bytes = u64::from_le_bytes(
-            <[u8; 8]>::try_from(&buffer[tags_start + 8..tags_start + 16]).map_err(|err| {
+            <[u8; 8]>::try_from(slice_at(buffer, tags_start + 8, 8)?).map_err(|err| {
                 ByteErrorType::ByteError(format!("tag bytes error - {}", err.to_string()))
             })?,
         );
 
-        let mut b = buffer.to_vec();
-        let mut tags_bytes =
-            &mut b[tags_start + 16..tags_start + 16 + number_of_tags_bytes as usize];
+        let tags_length = usize::try_from(number_of_tags_bytes)
+            .map_err(|_| ByteErrorType::ByteError("tag bytes error".to_string()))?;
+        let mut b = slice_at(buffer, tags_start + 16, tags_length)?.to_vec();
+        let mut tags_bytes = &mut b[..];
 
         let tags = if number_of_tags_bytes > 0 {
             tags_bytes.decode()?
@@ -393,7 +454,7 @@ impl DataItem {
             data: Data::None,
         };
 
-        Ok((data_item, tags_start + 16 + number_of_tags_bytes as usize))
+        Ok((data_item, tags_start + 16 + tags_length))
     }
 
     pub fn from_bytes(buffer: Vec<u8>) -> Result<Self, ByteErrorType> {
@@ -498,6 +559,13 @@ impl DataItem {
         }
     }
 
+    pub fn data_bytes(&self) -> Vec<u8> {
+        match &self.data {
+            Data::Bytes(d) => d.clone(),
+            Data::None => vec![],
+        }
+    }
+
     pub fn signature(&self) -> String {
         let sig_base64 = base64_url::encode(&self.signature);
         sig_base64
@@ -566,4 +634,51 @@ mod tests {
         let bundle_bytes = data_bundle.to_bytes();
         assert!(bundle_bytes.is_ok(), "Bundling failed");
     }
+
+    #[test]
+    fn test_bundle_from_bytes() {
+        let item_bytes =
+            base64_url::decode(&ITEM_STR.to_string()).expect("failed to encode data item");
+        let data_item = DataItem::from_bytes(item_bytes).expect("failed to build data item");
+        let mut data_bundle = DataBundle::new(vec![]);
+        data_bundle.add_item(data_item.clone());
+        data_bundle.add_item(data_item);
+        let bundle_bytes = data_bundle.to_bytes().expect("failed to bundle");
+
+        let parsed = DataBundle::from_bytes(&bundle_bytes, vec![]).expect("failed to parse bundle");
+        assert_eq!(parsed.items.len(), 2);
+        assert_eq!(
+            "6oYAxVAnH8yKsZKpMgHSbRv7uVWey68PAqYuSXeZBbg".to_string(),
+            parsed.items[1].id()
+        );
+        assert_eq!(parsed.to_bytes().expect("failed to bundle"), bundle_bytes);
+        assert!(DataBundle::from_bytes(&bundle_bytes[..100], vec![]).is_err());
+    }
+
+    #[test]
+    fn test_malformed_bytes_are_errors() {
+        let item_bytes =
+            base64_url::decode(&ITEM_STR.to_string()).expect("failed to encode data item");
+        let data_item =
+            DataItem::from_bytes(item_bytes.clone()).expect("failed to build data item");
+        let mut data_bundle = DataBundle::new(vec![]);
+        data_bundle.add_item(data_item);
+        let bundle_bytes = data_bundle.to_bytes().expect("failed to bundle");
+
+        // every truncation fails cleanly instead of panicking
+        for len in 0..item_bytes.len() {
+            let _ = DataItem::from_bytes(item_bytes[..len].to_vec());
+        }
+        for len in 0..bundle_bytes.len() {
+            assert!(DataBundle::from_bytes(&bundle_bytes[..len], vec![]).is_err());
+        }
+
+        // counts and sizes that would overflow the offsets
+        let mut huge_count = bundle_bytes.clone();
+        huge_count[..8].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(DataBundle::from_bytes(&huge_count, vec![]).is_err());
+        let mut huge_size = bundle_bytes;
+        huge_size[32..40].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(DataBundle::from_bytes(&huge_size, vec![]).is_err());
+    }
 }
@@ -1,5 +1,10 @@
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use futures::stream::BoxStream;
 use serde::Deserialize;
+use std::fmt;
+use std::io::Read;
+use std::sync::Arc;
 
 pub use super::json::{JsonErrorType, Message, PaginatedMessages, Process};
 pub use super::router::{ProcessScheduler, Scheduler};
@@ -74,41 +79,390 @@ pub trait Uploader: Send + Sync {
     fn upload(&self, tx: Vec<u8>) -> Result<(), UploaderErrorType>;
 }
 
+// what a NotFound error was looking for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Entity {
+    Message,
+    Assignment,
+    Process,
+    Scheduler,
+    ProcessScheduler,
+    Bundle,
+}
+
+impl fmt::Display for Entity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Entity::Message => write!(f, "Message"),
+            Entity::Assignment => write!(f, "Assignment"),
+            Entity::Process => write!(f, "Process"),
+            Entity::Scheduler => write!(f, "Scheduler"),
+            Entity::ProcessScheduler => write!(f, "Process scheduler"),
+            Entity::Bundle => write!(f, "Bundle"),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum StoreErrorType {
     DatabaseError(String),
-    NotFound(String),
+    // the id that was looked up, empty when any row would have done
+    NotFound(Entity, String),
     JsonError(String),
     EnvVarError(String),
     IntError(String),
     MessageExists(String),
+    NotAllowed(String),
+    // no pool connection freed up within DB_CONNECTION_TIMEOUT_SECS
+    PoolTimeout(String),
+    // postgres cancelled a query that ran past STATEMENT_TIMEOUT_MS
+    QueryTimeout(String),
+    // a requested range (bytes of a bundle, buckets) that can't be served
+    InvalidRange(String),
+    // reading or writing a backup stream failed
+    IoError(String),
+}
+
+/*
+    the message a client sees, the Debug output keeps
+    the underlying diesel/serde detail for the logs.
+    MessageExists, NotAllowed and InvalidRange only carry
+    messages written in the store so they pass through,
+    NotFound names the entity and leaves the id out
+*/
+impl fmt::Display for StoreErrorType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StoreErrorType::DatabaseError(_) => write!(f, "Database error"),
+            StoreErrorType::NotFound(entity, _) => write!(f, "{} not found", entity),
+            StoreErrorType::JsonError(_) => write!(f, "Stored data could not be read"),
+            StoreErrorType::EnvVarError(_) => write!(f, "Data store is misconfigured"),
+            StoreErrorType::IntError(_) => write!(f, "Invalid number in stored data"),
+            StoreErrorType::MessageExists(m) => write!(f, "{}", m),
+            StoreErrorType::NotAllowed(m) => write!(f, "{}", m),
+            StoreErrorType::PoolTimeout(_) => write!(f, "Database is busy, try again"),
+            StoreErrorType::QueryTimeout(_) => write!(f, "Database query timed out"),
+            StoreErrorType::InvalidRange(m) => write!(f, "{}", m),
+            StoreErrorType::IoError(_) => write!(f, "Backup stream could not be read or written"),
+        }
+    }
+}
+
+impl StoreErrorType {
+    // true when Display is generic and the detail only belongs in the logs
+    pub fn is_internal(&self) -> bool {
+        !matches!(
+            self,
+            StoreErrorType::NotFound(..)
+                | StoreErrorType::MessageExists(_)
+                | StoreErrorType::NotAllowed(_)
+                | StoreErrorType::InvalidRange(_)
+        )
+    }
+
+    /*
+        what a client is shown for this error, the detail
+        behind the generic ones is logged to logger
+    */
+    pub fn logged(self, logger: &dyn Log) -> String {
+        if self.is_internal() {
+            logger.error(format!("{:?}", self));
+        }
+        self.to_string()
+    }
+}
+
+// (message_id, assignment_id, process_id, timestamp, bundle)
+pub type ExportedMessage = (String, Option<String>, String, i64, Vec<u8>);
+
+// ExportedMessage without the bundle
+pub type ExportedMessageKey = (String, Option<String>, String, i64);
+
+#[derive(Debug)]
+pub struct DeleteReport {
+    pub processes_deleted: usize,
+    pub messages_deleted: usize,
+    pub process_schedulers_deleted: usize,
+}
+
+#[derive(Debug)]
+pub struct RenameReport {
+    pub processes_renamed: usize,
+    pub messages_renamed: usize,
+    pub process_schedulers_renamed: usize,
+}
+
+/*
+    what save_message did with a message, with the
+    row_id of its row for use as a cursor right away
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SaveOutcome {
+    Inserted { row_id: i32 },
+    // the same message was already stored at its process, epoch and nonce
+    AlreadyExists { row_id: i32 },
+}
+
+impl SaveOutcome {
+    pub fn row_id(&self) -> i32 {
+        match self {
+            SaveOutcome::Inserted { row_id } => *row_id,
+            SaveOutcome::AlreadyExists { row_id } => *row_id,
+        }
+    }
+}
+
+/*
+    result of save_messages_batch, saved counts the
+    messages in chunks that committed before error and
+    skipped the ones whose row was already there
+*/
+#[derive(Debug)]
+pub struct BatchSaveReport {
+    pub saved: usize,
+    pub skipped: usize,
+    pub error: Option<StoreErrorType>,
+}
+
+// which id get_message_matched found the row by
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatchedBy {
+    Message,
+    Assignment,
+}
+
+// direction get_messages walks a process's timestamps in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortOrder {
+    Asc,
+    Desc,
+}
+
+/*
+    how get_messages walks and bounds a process's messages,
+    from and to follow the order. the default, from exclusive
+    and to inclusive. next_cursor can always be passed back as
+    from, it carries the row_id so the flags don't apply to it.
+    from_inclusive with to exclusive gives half open [from, to)
+    windows that stitch together without gaps or repeats
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MessageRange {
+    pub order: SortOrder,
+    pub from_inclusive: bool,
+    pub to_inclusive: bool,
+}
+
+impl Default for MessageRange {
+    fn default() -> Self {
+        MessageRange {
+            order: SortOrder::Asc,
+            from_inclusive: false,
+            to_inclusive: true,
+        }
+    }
+}
+
+/*
+    one poll of a process's (or the whole node's) messages,
+    max_row_id is the newest row in scope when the page was
+    read, None if there are no messages yet
+*/
+pub struct TailedMessages {
+    pub messages: Vec<Message>,
+    pub last_row_id: Option<i32>,
+    pub max_row_id: Option<i32>,
 }
 
+/*
+    called with (message_id, process_id) after a message
+    save commits. it runs on the saving task so it should
+    only hand the ids off, an Err is logged and ignored
+*/
+pub type SaveHook = Arc<dyn Fn(&str, &str) -> Result<(), String> + Send + Sync>;
+
+#[async_trait]
 pub trait DataStore: Send + Sync {
-    fn save_process(&self, process: &Process, bundle_in: &[u8]) -> Result<String, StoreErrorType>;
+    async fn save_process(
+        &self,
+        process: &Process,
+        bundle_in: &[u8],
+    ) -> Result<String, StoreErrorType>;
     fn get_process(&self, process_id_in: &str) -> Result<Process, StoreErrorType>;
-    fn save_message(&self, message: &Message, bundle_in: &[u8]) -> Result<String, StoreErrorType>;
+    fn get_processes(&self, process_ids: &[String]) -> Result<Vec<Process>, StoreErrorType>;
+    fn get_process_checkpoint(
+        &self,
+        process_id_in: &str,
+    ) -> Result<(Process, Option<Message>), StoreErrorType>;
+    fn process_exists(&self, process_id_in: &str) -> Result<bool, StoreErrorType>;
+    fn list_processes(
+        &self,
+        after_row_id: Option<i32>,
+        limit: i64,
+    ) -> Result<Vec<(i32, String)>, StoreErrorType>;
+    async fn save_message(
+        &self,
+        message: &Message,
+        bundle_in: &[u8],
+    ) -> Result<SaveOutcome, StoreErrorType>;
+    async fn queued_save_message(
+        &self,
+        message: &Message,
+        bundle_in: &[u8],
+    ) -> Result<SaveOutcome, StoreErrorType>;
+    async fn save_messages_batch(&self, batch: Vec<(Message, Vec<u8>)>) -> BatchSaveReport;
     fn get_messages(
         &self,
         process_id_in: &str,
         from: &Option<String>,
         to: &Option<String>,
         limit: &Option<i32>,
+        include_total: bool,
+        range: MessageRange,
     ) -> Result<PaginatedMessages, StoreErrorType>;
+    fn get_messages_in_epoch(
+        &self,
+        process_id_in: &str,
+        epoch_in: i32,
+        after_nonce: &Option<i32>,
+        limit: &Option<i32>,
+    ) -> Result<PaginatedMessages, StoreErrorType>;
+    fn iter_process_messages(
+        &self,
+        process_id_in: &str,
+    ) -> BoxStream<'static, Result<Message, StoreErrorType>>;
     fn get_message(&self, message_id_in: &str) -> Result<Message, StoreErrorType>;
+    fn get_message_matched(&self, tx_id: &str) -> Result<(Message, MatchedBy), StoreErrorType>;
+    fn get_bundle_bytes(&self, tx_id: &str) -> Result<Vec<u8>, StoreErrorType>;
+    fn read_bundle_range(
+        &self,
+        tx_id: &str,
+        offset: u64,
+        len: u64,
+    ) -> Result<Vec<u8>, StoreErrorType>;
+    fn bundle_size_and_stream(
+        &self,
+        tx_id: &str,
+    ) -> Result<(u64, Box<dyn Read + Send>), StoreErrorType>;
+    fn verify_bundle_id(&self, message_id_in: &str) -> Result<bool, StoreErrorType>;
+    fn verify_process_bundles(&self, process_id_in: &str) -> Result<Vec<String>, StoreErrorType>;
+    fn find_duplicate_message_bundles(
+        &self,
+        process_id_in: &str,
+    ) -> Result<Vec<String>, StoreErrorType>;
+    fn get_message_by_hash_chain(
+        &self,
+        process_id_in: &str,
+        hash_chain_in: &str,
+    ) -> Result<Option<Message>, StoreErrorType>;
+    fn get_message_id_for_assignment(
+        &self,
+        assignment_id_in: &str,
+    ) -> Result<String, StoreErrorType>;
+    fn get_assignments_for_message(
+        &self,
+        message_id_in: &str,
+    ) -> Result<Vec<Message>, StoreErrorType>;
     fn get_latest_message(&self, process_id_in: &str) -> Result<Option<Message>, StoreErrorType>;
+    fn get_process_storage_bytes(&self, process_id_in: &str) -> Result<i64, StoreErrorType>;
+    fn get_message_count(&self, process_id_in: Option<&str>) -> Result<i64, StoreErrorType>;
+    fn get_message_rate(
+        &self,
+        process_id_in: &str,
+        bucket_seconds: i64,
+        from: i64,
+        to: i64,
+    ) -> Result<Vec<(i64, i64)>, StoreErrorType>;
+    fn get_dormant_processes(&self, older_than: i64) -> Result<Vec<String>, StoreErrorType>;
+    fn reindex_process(&self, process_id_in: &str) -> Result<usize, StoreErrorType>;
+    fn reindex_processes(
+        &self,
+        after_row_id: Option<i32>,
+        limit: i64,
+    ) -> Result<(usize, Option<i32>), StoreErrorType>;
+    fn move_bundles_to_table(
+        &self,
+        after_row_id: i32,
+        limit: i64,
+    ) -> Result<(usize, Option<i32>), StoreErrorType>;
+    fn compress_stored_bundles(
+        &self,
+        after_row_id: i32,
+        limit: i64,
+    ) -> Result<(usize, Option<i32>), StoreErrorType>;
+    fn count_messages_after_nonce(
+        &self,
+        process_id_in: &str,
+        nonce_in: i32,
+    ) -> Result<i64, StoreErrorType>;
+    fn get_messages_for_export(
+        &self,
+        after_row_id: i32,
+        limit: i64,
+    ) -> Result<(Vec<ExportedMessage>, Option<i32>), StoreErrorType>;
+    fn get_message_keys_for_export(
+        &self,
+        after_row_id: i32,
+        limit: i64,
+    ) -> Result<(Vec<ExportedMessageKey>, Option<i32>), StoreErrorType>;
+    fn get_messages_by_tag(
+        &self,
+        process_id_in: &str,
+        tag_name: &str,
+        tag_value: &str,
+        limit: i64,
+    ) -> Result<Vec<Message>, StoreErrorType>;
+    fn get_messages_after_row_id(
+        &self,
+        process_id_in: &str,
+        after: i32,
+        limit: i64,
+    ) -> Result<TailedMessages, StoreErrorType>;
+    fn get_latest_messages_global(
+        &self,
+        after_row_id: i32,
+        limit: i64,
+    ) -> Result<TailedMessages, StoreErrorType>;
+    fn get_messages_multi(
+        &self,
+        process_ids: &[String],
+        after_row_id: i32,
+        limit: i64,
+    ) -> Result<TailedMessages, StoreErrorType>;
+    fn get_recently_inserted(
+        &self,
+        since: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<Vec<Message>, StoreErrorType>;
     fn save_process_scheduler(
         &self,
         process_scheduler: &ProcessScheduler,
-    ) -> Result<String, StoreErrorType>;
+    ) -> Result<bool, StoreErrorType>;
     fn get_process_scheduler(
         &self,
         process_id_in: &str,
     ) -> Result<ProcessScheduler, StoreErrorType>;
     fn save_scheduler(&self, scheduler: &Scheduler) -> Result<String, StoreErrorType>;
+    fn save_schedulers_batch(&self, schedulers: &[Scheduler]) -> Result<usize, StoreErrorType>;
     fn update_scheduler(&self, scheduler: &Scheduler) -> Result<String, StoreErrorType>;
+    fn increment_scheduler_process_count(
+        &self,
+        row_id_in: i32,
+        delta: i32,
+    ) -> Result<String, StoreErrorType>;
+    fn delete_scheduler(&self, row_id_in: i32) -> Result<String, StoreErrorType>;
+    fn reassign_process(
+        &self,
+        process_id_in: &str,
+        new_scheduler_row_id: i32,
+    ) -> Result<String, StoreErrorType>;
     fn get_scheduler(&self, row_id_in: &i32) -> Result<Scheduler, StoreErrorType>;
     fn get_scheduler_by_url(&self, url_in: &String) -> Result<Scheduler, StoreErrorType>;
     fn get_all_schedulers(&self) -> Result<Vec<Scheduler>, StoreErrorType>;
+    fn get_all_schedulers_by_url(&self) -> Result<Vec<Scheduler>, StoreErrorType>;
+    fn migration_status(&self) -> Result<Vec<(String, bool)>, StoreErrorType>;
+    fn get_least_loaded_scheduler(&self) -> Result<Scheduler, StoreErrorType>;
     fn check_existing_message(&self, message: &Message) -> Result<(), StoreErrorType>;
+    fn delete_process(&self, process_id_in: &str) -> Result<DeleteReport, StoreErrorType>;
+    fn rename_process(&self, old_id: &str, new_id: &str) -> Result<RenameReport, StoreErrorType>;
 }
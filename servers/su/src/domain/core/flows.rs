@@ -8,7 +8,10 @@ use super::builder::Builder;
 use super::json::{Message, Process};
 use super::scheduler;
 
-use super::dal::{Config, DataStore, Gateway, Log, Signer, Uploader, Wallet};
+use super::dal::{
+    Config, DataStore, Gateway, Log, MessageRange, SaveOutcome, Signer, SortOrder, StoreErrorType,
+    Uploader, Wallet,
+};
 
 pub struct Deps {
     pub data_store: Arc<dyn DataStore>,
@@ -38,6 +41,11 @@ pub fn init_builder(deps: &Arc<Deps>) -> Result<Builder, String> {
     return Ok(builder);
 }
 
+// store errors reach the client by their Display, see StoreErrorType::logged
+pub fn store_error(deps: &Deps, error: StoreErrorType) -> String {
+    error.logged(&*deps.logger)
+}
+
 async fn upload(deps: &Arc<Deps>, build_result: Vec<u8>) -> Result<String, String> {
     let uploaded_tx = &deps.uploader.upload(build_result)?;
     let result = match serde_json::to_string(&uploaded_tx) {
@@ -47,6 +55,35 @@ async fn upload(deps: &Arc<Deps>, build_result: Vec<u8>) -> Result<String, Strin
     Ok(result)
 }
 
+/*
+    a save that finds the same message already stored
+    was a retry, whoever stored it has uploaded it. saves
+    go through the ingest queue, which saves directly
+    when INGEST_WORKERS is unset
+*/
+async fn save_and_upload(
+    deps: &Arc<Deps>,
+    message: &Message,
+    binary: Vec<u8>,
+) -> Result<(), String> {
+    match deps
+        .data_store
+        .queued_save_message(message, &binary)
+        .await
+        .map_err(|e| store_error(deps, e))?
+    {
+        SaveOutcome::Inserted { .. } => {
+            deps.logger.log(format!("saved message - {:?}", message));
+            upload(deps, binary).await?;
+        }
+        SaveOutcome::AlreadyExists { .. } => {
+            deps.logger
+                .log(format!("message already saved - {:?}", message));
+        }
+    }
+    Ok(())
+}
+
 async fn assignment_only(
     deps: Arc<Deps>,
     process_id: String,
@@ -63,7 +100,10 @@ async fn assignment_only(
         .update_schedule_info(&mut *schedule_info, process_id.clone())
         .await?;
 
-    let process = deps.data_store.get_process(&process_id)?;
+    let process = deps
+        .data_store
+        .get_process(&process_id)
+        .map_err(|e| store_error(&deps, e))?;
     let build_result = builder
         .build_assignment(
             assign.clone(),
@@ -75,10 +115,7 @@ async fn assignment_only(
         .await?;
 
     let message = Message::from_bundle(&build_result.bundle)?;
-    deps.data_store
-        .save_message(&message, &build_result.binary)?;
-    deps.logger.log(format!("saved message - {:?}", &message));
-    upload(&deps, build_result.binary.to_vec()).await?;
+    save_and_upload(&deps, &message, build_result.binary.to_vec()).await?;
     drop(schedule_info);
 
     match system_time_u64() {
@@ -151,7 +188,9 @@ pub async fn write_item(
             upload(&deps, build_result.binary.to_vec()).await?;
             let process = Process::from_bundle(&build_result.bundle)?;
             deps.data_store
-                .save_process(&process, &build_result.binary)?;
+                .save_process(&process, &build_result.binary)
+                .await
+                .map_err(|e| store_error(&deps, e))?;
             deps.logger.log(format!("saved process - {:?}", &process));
             drop(schedule_info);
             match system_time_u64() {
@@ -177,10 +216,7 @@ pub async fn write_item(
 
             let build_result = builder.build_message(input, &*updated_info).await?;
             let message = Message::from_bundle(&build_result.bundle)?;
-            deps.data_store
-                .save_message(&message, &build_result.binary)?;
-            deps.logger.log(format!("saved message - {:?}", &message));
-            upload(&deps, build_result.binary.to_vec()).await?;
+            save_and_upload(&deps, &message, build_result.binary.to_vec()).await?;
             drop(schedule_info);
             match system_time_u64() {
                 Ok(timestamp) => {
@@ -198,12 +234,36 @@ pub async fn write_item(
     }
 }
 
+/*
+    the MessageRange for a read_message_data query, unset
+    inclusive flags keep the defaults
+*/
+pub fn message_range(
+    sort: Option<String>,
+    from_inclusive: Option<bool>,
+    to_inclusive: Option<bool>,
+) -> Result<MessageRange, String> {
+    let default = MessageRange::default();
+    let order = match sort.as_deref() {
+        None | Some("asc") => SortOrder::Asc,
+        Some("desc") => SortOrder::Desc,
+        Some(other) => return Err(format!("Invalid sort {}, expected asc or desc", other)),
+    };
+    Ok(MessageRange {
+        order,
+        from_inclusive: from_inclusive.unwrap_or(default.from_inclusive),
+        to_inclusive: to_inclusive.unwrap_or(default.to_inclusive),
+    })
+}
+
 pub async fn read_message_data(
     deps: Arc<Deps>,
     tx_id: String,
     from: Option<String>,
     to: Option<String>,
     limit: Option<i32>,
+    include_total: bool,
+    range: MessageRange,
 ) -> Result<String, String> {
     if let Ok(message) = deps.data_store.get_message(&tx_id) {
         let result = match serde_json::to_string(&message) {
@@ -213,8 +273,11 @@ pub async fn read_message_data(
         return Ok(result);
     }
 
-    if let Ok(_) = deps.data_store.get_process(&tx_id) {
-        let messages = deps.data_store.get_messages(&tx_id, &from, &to, &limit)?;
+    if let Ok(true) = deps.data_store.process_exists(&tx_id) {
+        let messages = deps
+            .data_store
+            .get_messages(&tx_id, &from, &to, &limit, include_total, range)
+            .map_err(|e| store_error(&deps, e))?;
         let result = match serde_json::to_string(&messages) {
             Ok(r) => r,
             Err(e) => return Err(format!("{:?}", e)),
@@ -226,7 +289,10 @@ pub async fn read_message_data(
 }
 
 pub async fn read_process(deps: Arc<Deps>, process_id: String) -> Result<String, String> {
-    let process = deps.data_store.get_process(&process_id)?;
+    let process = deps
+        .data_store
+        .get_process(&process_id)
+        .map_err(|e| store_error(&deps, e))?;
     let result = match serde_json::to_string(&process) {
         Ok(r) => r,
         Err(e) => return Err(format!("{:?}", e)),
@@ -268,6 +334,25 @@ pub async fn timestamp(deps: Arc<Deps>) -> Result<String, String> {
     }
 }
 
+/*
+    returns whether every migration is applied along
+    with the json listing, so the route can fail
+    readiness checks on a node behind on its schema
+*/
+pub async fn migration_status(deps: Arc<Deps>) -> Result<(bool, String), String> {
+    let status = deps
+        .data_store
+        .migration_status()
+        .map_err(|e| store_error(&deps, e))?;
+    let up_to_date = status.iter().all(|(_, applied)| *applied);
+    let migrations: Vec<_> = status
+        .into_iter()
+        .map(|(name, applied)| json!({ "name": name, "applied": applied }))
+        .collect();
+    let response_json = json!({ "up_to_date": up_to_date, "migrations": migrations });
+    Ok((up_to_date, response_json.to_string()))
+}
+
 pub async fn health(deps: Arc<Deps>) -> Result<String, String> {
     match system_time() {
         Ok(timestamp) => {
@@ -280,4 +365,4 @@ pub async fn health(deps: Arc<Deps>) -> Result<String, String> {
         }
         Err(e) => Err(format!("{:?}", e)),
     }
-}
\ No newline at end of file
+}
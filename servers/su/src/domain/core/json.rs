@@ -99,6 +99,12 @@ pub struct PaginatedMessages {
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct PageInfo {
     pub has_next_page: bool,
+    // only present when the caller asked for it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total_count: Option<i64>,
+    // pass back as-is to read the next page, only present when there is one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -168,12 +174,13 @@ impl Process {
 
 impl Message {
     pub fn from_bundle(data_bundle: &DataBundle) -> Result<Self, JsonErrorType> {
-        let id = data_bundle.items[0].id().clone();
-        let tags = data_bundle.items[0].tags();
-        let owner = data_bundle.items[0].owner().clone();
-        let target = data_bundle.items[0].target().clone();
-        let signature = data_bundle.items[0].signature().clone();
-        let anchor = data_bundle.items[0].anchor().clone();
+        let assignment_item = data_bundle.items.first().ok_or("Bundle has no items")?;
+        let id = assignment_item.id().clone();
+        let tags = assignment_item.tags();
+        let owner = assignment_item.owner().clone();
+        let target = assignment_item.target().clone();
+        let signature = assignment_item.signature().clone();
+        let anchor = assignment_item.anchor().clone();
 
         let ac = anchor.clone();
         let anchor_r = match &*anchor {
@@ -246,6 +253,45 @@ impl Message {
         })
     }
 
+    /*
+        the bundle column holds the su's signed data item
+        with the ANS-104 bundle as its data. it can't be
+        rebuilt from the json since the random anchors and
+        the outer signature aren't kept there, so to check
+        the two agree we go the other way and rebuild the
+        Message from the stored bytes
+    */
+    pub fn from_bundle_bytes(bundle: Vec<u8>) -> Result<Self, JsonErrorType> {
+        let bundle_item = DataItem::from_bytes(bundle)?;
+        let data_bundle = DataBundle::from_bytes(&bundle_item.data_bytes(), bundle_item.tags())?;
+        Message::from_bundle(&data_bundle)
+    }
+
+    /*
+        sha256 of the message data item inside a stored
+        bundle, None for an assignment only bundle. rows
+        for one message_id should all agree on this no
+        matter which assignment wraps it
+    */
+    pub fn message_item_hash(bundle: Vec<u8>) -> Result<Option<String>, JsonErrorType> {
+        let bundle_item = DataItem::from_bytes(bundle)?;
+        let data_bundle = DataBundle::from_bytes(&bundle_item.data_bytes(), bundle_item.tags())?;
+        match data_bundle.items.get(1) {
+            Some(message_item) => Ok(Some(base64_url::encode(&hash(&message_item.as_bytes()?)))),
+            None => Ok(None),
+        }
+    }
+
+    /*
+        true when the stored bundle still decodes to this
+        exact Message, false means the json and the bundle
+        have diverged
+    */
+    pub fn matches_bundle(&self, bundle: Vec<u8>) -> Result<bool, JsonErrorType> {
+        let rebuilt = Message::from_bundle_bytes(bundle)?;
+        Ok(serde_json::to_value(self)? == serde_json::to_value(&rebuilt)?)
+    }
+
     pub fn epoch(&self) -> Result<i32, JsonErrorType> {
         let epoch_tag = self
             .assignment
@@ -314,6 +360,22 @@ impl Message {
         Ok(assignment_id)
     }
 
+    /*
+        The assignment_id column value for this message.
+        Every message this su schedules is wrapped in an
+        assignment, so this is Some for anything written
+        now. SQL NULL is reserved for rows stored before
+        assignments were added, an empty string is never
+        written, it is mapped to NULL here so lookups by
+        assignment id can't match on "".
+    */
+    pub fn stored_assignment_id(&self) -> Option<String> {
+        match self.assignment.id.is_empty() {
+            true => None,
+            false => Some(self.assignment.id.clone()),
+        }
+    }
+
     pub fn process_id(&self) -> Result<String, JsonErrorType> {
         let process_tag = self
             .assignment
@@ -330,7 +392,7 @@ impl Message {
         handle assign. If the shape changes again
         we can modify this mapping to handle it.
     */
-    pub fn from_val(value: &serde_json::Value, bundle: Vec<u8>) -> Result<Self, JsonErrorType> {
+    pub fn from_val(value: &serde_json::Value, bundle: &[u8]) -> Result<Self, JsonErrorType> {
         match value.get("assignment") {
             Some(_) => {
                 /*
@@ -366,7 +428,7 @@ impl Message {
                     target: message_target,
                 });
 
-                let bundle_data_item = DataItem::from_bytes(bundle)?;
+                let bundle_data_item = DataItem::from_bytes(bundle.to_vec())?;
 
                 let owner = bundle_data_item.owner();
                 let owner_bytes = base64_url::decode(&owner)?;
@@ -447,8 +509,17 @@ impl PaginatedMessages {
     pub fn from_messages(
         messages: Vec<Message>,
         has_next_page: bool,
+        total_count: Option<i64>,
+        next_cursor: Option<String>,
     ) -> Result<Self, JsonErrorType> {
-        let page_info = PageInfo { has_next_page };
+        let page_info = PageInfo {
+            has_next_page,
+            total_count,
+            next_cursor: match has_next_page {
+                true => next_cursor,
+                false => None,
+            },
+        };
 
         let edges = messages
             .into_iter()
@@ -513,6 +584,125 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_message_from_empty_bundle() {
+        let data_bundle = DataBundle::new(vec![]);
+        assert!(Message::from_bundle(&data_bundle).is_err());
+    }
+
+    #[test]
+    fn test_next_cursor() {
+        let paginated =
+            PaginatedMessages::from_messages(vec![], true, None, Some("12".to_string()))
+                .expect("failed to paginate");
+        assert_eq!(paginated.page_info.next_cursor, Some("12".to_string()));
+        let json = serde_json::to_value(&paginated).expect("failed to serialize");
+        assert_eq!(json["page_info"]["next_cursor"], "12");
+
+        // there is nothing to point at on the last page
+        let paginated =
+            PaginatedMessages::from_messages(vec![], false, None, Some("12".to_string()))
+                .expect("failed to paginate");
+        assert_eq!(paginated.page_info.next_cursor, None);
+        let json = serde_json::to_value(&paginated).expect("failed to serialize");
+        assert!(json["page_info"].get("next_cursor").is_none());
+    }
+
+    #[test]
+    fn test_stored_assignment_id() {
+        let item_bytes =
+            base64_url::decode(&ITEM_STR.to_string()).expect("failed to encode data item");
+        let assignment_item_bytes = base64_url::decode(&ASSIGNMENT_ITEM_STR.to_string())
+            .expect("failed to encode data item");
+        let data_item = DataItem::from_bytes(item_bytes).expect("failed to build data item");
+        let assignment_data_item =
+            DataItem::from_bytes(assignment_item_bytes).expect("failed to build data item");
+        let mut data_bundle = DataBundle::new(vec![]);
+        data_bundle.add_item(assignment_data_item);
+        data_bundle.add_item(data_item);
+        let mut message = Message::from_bundle(&data_bundle).expect("failed to create message");
+        assert_eq!(
+            message.stored_assignment_id(),
+            Some(message.assignment.id.clone())
+        );
+
+        message.assignment.id = "".to_string();
+        assert_eq!(message.stored_assignment_id(), None);
+    }
+
+    #[test]
+    fn test_message_matches_bundle() {
+        let item_bytes =
+            base64_url::decode(&ITEM_STR.to_string()).expect("failed to encode data item");
+        let assignment_item_bytes = base64_url::decode(&ASSIGNMENT_ITEM_STR.to_string())
+            .expect("failed to encode data item");
+        let data_item = DataItem::from_bytes(item_bytes).expect("failed to build data item");
+        let assignment_data_item =
+            DataItem::from_bytes(assignment_item_bytes).expect("failed to build data item");
+        let tags = vec![
+            Tag::new("Bundle-Format", "binary"),
+            Tag::new("Bundle-Version", "2.0.0"),
+        ];
+        let mut data_bundle = DataBundle::new(tags.clone());
+        data_bundle.add_item(assignment_data_item);
+        data_bundle.add_item(data_item);
+        let buffer = data_bundle.to_bytes().expect("failed to bundle");
+
+        // stands in for the su signed item wrapping the bundle
+        let mut bundle_item =
+            DataItem::new(vec![], buffer, tags, vec![1u8; 512]).expect("failed to build item");
+        bundle_item.signature = vec![2u8; 512];
+        let bundle_bytes = bundle_item.as_bytes().expect("failed to convert to bytes");
+
+        let mut message = Message::from_bundle(&data_bundle).expect("failed to create message");
+        assert!(message
+            .matches_bundle(bundle_bytes.clone())
+            .expect("failed to check bundle"));
+
+        message.assignment.tags.pop();
+        assert!(!message
+            .matches_bundle(bundle_bytes)
+            .expect("failed to check bundle"));
+    }
+
+    #[test]
+    fn test_message_item_hash() {
+        let item_bytes =
+            base64_url::decode(&ITEM_STR.to_string()).expect("failed to encode data item");
+        let assignment_item_bytes = base64_url::decode(&ASSIGNMENT_ITEM_STR.to_string())
+            .expect("failed to encode data item");
+        let tags = vec![
+            Tag::new("Bundle-Format", "binary"),
+            Tag::new("Bundle-Version", "2.0.0"),
+        ];
+        let wrap = |items: Vec<Vec<u8>>, signature: u8| {
+            let mut data_bundle = DataBundle::new(tags.clone());
+            for item in items {
+                data_bundle.add_item(DataItem::from_bytes(item).expect("failed to build item"));
+            }
+            let buffer = data_bundle.to_bytes().expect("failed to bundle");
+            let mut bundle_item = DataItem::new(vec![], buffer, tags.clone(), vec![1u8; 512])
+                .expect("failed to build item");
+            bundle_item.signature = vec![signature; 512];
+            bundle_item.as_bytes().expect("failed to convert to bytes")
+        };
+
+        let first = wrap(vec![assignment_item_bytes.clone(), item_bytes.clone()], 2);
+        let second = wrap(vec![assignment_item_bytes.clone(), item_bytes], 3);
+        let first_hash = Message::message_item_hash(first).expect("failed to hash");
+        assert!(first_hash.is_some());
+        assert_eq!(
+            first_hash,
+            Message::message_item_hash(second).expect("failed to hash")
+        );
+
+        let assignment_only = wrap(vec![assignment_item_bytes], 2);
+        assert_eq!(
+            Message::message_item_hash(assignment_only).expect("failed to hash"),
+            None
+        );
+    }
+
     #[test]
     fn test_process_from_bundle() {
         let d_item_string = PROCESS_ITEM_STR.to_string();
@@ -1,5 +1,5 @@
-use crate::domain::core::dal::StoreErrorType;
-use crate::domain::flows::{init_builder, Deps};
+use crate::domain::core::dal::{Entity, StoreErrorType};
+use crate::domain::flows::{init_builder, store_error, Deps};
 use serde::Deserialize;
 use std::{fmt::Debug, sync::Arc};
 use tokio::{fs::File, io::AsyncReadExt};
@@ -48,21 +48,22 @@ pub async fn init_schedulers(deps: Arc<Deps>) -> Result<String, String> {
         serde_json::from_str(&contents).map_err(|e| format!("Failed to parse JSON: {}", e))?;
 
     /*
-        Iterate over the URLs and check each one
-        if the scheduler doesnt exist yet create it
+        write every scheduler in one go, the store skips
+        urls that already exist
     */
-    for entry in urls {
-        if let Err(StoreErrorType::NotFound(_)) = deps.data_store.get_scheduler_by_url(&entry.url) {
-            let scheduler = Scheduler {
-                row_id: None,
-                url: entry.url.clone(),
-                process_count: 0,
-            };
-            deps.data_store.save_scheduler(&scheduler)?;
-            deps.logger
-                .log(format!("saved new scheduler: {}", entry.url));
-        }
-    }
+    let schedulers: Vec<Scheduler> = urls
+        .into_iter()
+        .map(|entry| Scheduler {
+            row_id: None,
+            url: entry.url,
+            process_count: 0,
+        })
+        .collect();
+    let saved = deps
+        .data_store
+        .save_schedulers_batch(&schedulers)
+        .map_err(|e| store_error(&deps, e))?;
+    deps.logger.log(format!("saved {} new schedulers", saved));
 
     Ok("schedulers initialized".to_string())
 }
@@ -79,10 +80,14 @@ pub async fn redirect_process_id(
     let pid = process_id.ok_or("No process-id query parameter provided")?;
 
     // every other process_id, redirect
-    let process_scheduler = deps.data_store.get_process_scheduler(&pid)?;
+    let process_scheduler = deps
+        .data_store
+        .get_process_scheduler(&pid)
+        .map_err(|e| store_error(&deps, e))?;
     let scheduler = deps
         .data_store
-        .get_scheduler(&process_scheduler.scheduler_row_id)?;
+        .get_scheduler(&process_scheduler.scheduler_row_id)
+        .map_err(|e| store_error(&deps, e))?;
     Ok(Some(scheduler.url))
 }
 
@@ -105,10 +110,14 @@ pub async fn redirect_tx_id(
         Err(_) => process_id.ok_or("Unable to locate process, if this is a message id query be sure to pass the process-id query parameter")?,
     };
 
-    let process_scheduler = deps.data_store.get_process_scheduler(&process_to_query)?;
+    let process_scheduler = deps
+        .data_store
+        .get_process_scheduler(&process_to_query)
+        .map_err(|e| store_error(&deps, e))?;
     let scheduler = deps
         .data_store
-        .get_scheduler(&process_scheduler.scheduler_row_id)?;
+        .get_scheduler(&process_scheduler.scheduler_row_id)
+        .map_err(|e| store_error(&deps, e))?;
     Ok(Some(scheduler.url))
 }
 
@@ -131,7 +140,8 @@ pub async fn redirect_data_item(
             Ok(process_scheduler) => {
                 let scheduler = deps
                     .data_store
-                    .get_scheduler(&process_scheduler.scheduler_row_id)?;
+                    .get_scheduler(&process_scheduler.scheduler_row_id)
+                    .map_err(|e| store_error(&deps, e))?;
                 return Ok(Some(scheduler.url));
             }
             Err(_) => return Err("Unable to locate scheduler for process-id".to_string()),
@@ -154,32 +164,47 @@ pub async fn redirect_data_item(
                 new process so we need to generate a
                 process_schedulers record and return the url
             */
-            let mut schedulers = deps.data_store.get_all_schedulers()?;
-            if let Some(min_scheduler) = schedulers.iter_mut().min_by_key(|s| s.process_count) {
-                min_scheduler.process_count += 1;
-                deps.data_store.update_scheduler(min_scheduler)?;
-
-                let scheduler_row_id = if let Some(min_scheduler_row_id) = min_scheduler.row_id {
-                    min_scheduler_row_id
-                } else {
-                    /*
-                        this should be unreachable but return an error
-                        just in case so the router doesn't crash
-                    */
-                    return Err("Missing id on scheduler".to_string());
-                };
-
-                let process_scheduler = ProcessScheduler {
-                    row_id: None,
-                    scheduler_row_id: scheduler_row_id,
-                    process_id: id,
-                };
-                deps.data_store.save_process_scheduler(&process_scheduler)?;
-
-                Ok(Some(min_scheduler.url.clone()))
+            let min_scheduler = match deps.data_store.get_least_loaded_scheduler() {
+                Ok(scheduler) => scheduler,
+                Err(StoreErrorType::NotFound(Entity::Scheduler, _)) => {
+                    return Err("Could not find a scheduler to assign".to_string())
+                }
+                Err(e) => return Err(store_error(&deps, e)),
+            };
+
+            let scheduler_row_id = if let Some(min_scheduler_row_id) = min_scheduler.row_id {
+                min_scheduler_row_id
             } else {
-                Err("Could not find a scheduler to assign".to_string())
+                /*
+                    this should be unreachable but return an error
+                    just in case so the router doesn't crash
+                */
+                return Err("Missing id on scheduler".to_string());
+            };
+
+            let process_scheduler = ProcessScheduler {
+                row_id: None,
+                scheduler_row_id: scheduler_row_id,
+                process_id: id.clone(),
+            };
+            let saved = deps
+                .data_store
+                .save_process_scheduler(&process_scheduler)
+                .map_err(|e| store_error(&deps, e))?;
+            if saved {
+                return Ok(Some(min_scheduler.url.clone()));
             }
+
+            // a retried spawn goes wherever the process was first mapped
+            let process_scheduler = deps
+                .data_store
+                .get_process_scheduler(&id)
+                .map_err(|e| store_error(&deps, e))?;
+            let scheduler = deps
+                .data_store
+                .get_scheduler(&process_scheduler.scheduler_row_id)
+                .map_err(|e| store_error(&deps, e))?;
+            Ok(Some(scheduler.url))
         }
         "Message" => {
             /*
@@ -190,7 +215,8 @@ pub async fn redirect_data_item(
                 Ok(process_scheduler) => {
                     let scheduler = deps
                         .data_store
-                        .get_scheduler(&process_scheduler.scheduler_row_id)?;
+                        .get_scheduler(&process_scheduler.scheduler_row_id)
+                        .map_err(|e| store_error(&deps, e))?;
                     Ok(Some(scheduler.url))
                 }
                 Err(_) => Err("Unable to locate scheduler for message target".to_string()),
@@ -157,7 +157,7 @@ async fn fetch_values(
 
     let latest_message = match deps.data_store.get_latest_message(process_id) {
         Ok(m) => m,
-        Err(e) => return Err(format!("{:?}", e)),
+        Err(e) => return Err(e.logged(&*deps.logger)),
     };
 
     match latest_message {
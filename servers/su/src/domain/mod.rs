@@ -1,4 +1,5 @@
 use std::sync::Arc;
+use std::time::Duration;
 
 mod clients;
 mod config;
@@ -6,28 +7,100 @@ mod core;
 mod logger;
 
 use clients::{
-    gateway::ArweaveGateway, signer::ArweaveSigner, store::StoreClient, uploader::UploaderClient,
-    wallet::FileWallet,
+    gateway::ArweaveGateway, signer::ArweaveSigner, uploader::UploaderClient, wallet::FileWallet,
 };
 use config::AoConfig;
 use core::dal::{Config, Gateway, Log};
 use logger::SuLog;
 
+pub use core::dal::SaveHook;
+// for ad hoc queries through StoreClient::with_read_conn and backups
+pub use clients::store::{ExportFormat, ExportStats, ImportStats, StoreClient};
 pub use core::flows;
 pub use core::router;
 pub use flows::Deps;
 
 pub async fn init_deps(mode: Option<String>) -> Arc<Deps> {
+    init_deps_with_save_hook(mode, None).await
+}
+
+/*
+    init_deps with a hook the store calls after every
+    committed message save, for mirroring messages to
+    another system without polling
+*/
+pub async fn init_deps_with_save_hook(
+    mode: Option<String>,
+    save_hook: Option<SaveHook>,
+) -> Arc<Deps> {
     let logger: Arc<dyn Log> = SuLog::init();
 
-    let data_store = Arc::new(StoreClient::new().expect("Failed to create StoreClient"));
+    let config = Arc::new(AoConfig::new(mode).expect("Failed to read configuration"));
+
+    let mut store =
+        StoreClient::from_config(&config, logger.clone()).expect("Failed to create StoreClient");
+    if let Some(hook) = save_hook {
+        store = store.with_save_hook(hook);
+    }
+    store = store.with_ingest_queue(config.ingest_workers, config.ingest_queue_depth);
+    let data_store = Arc::new(store);
 
     match data_store.run_migrations() {
         Ok(m) => logger.log(m),
         Err(e) => logger.log(format!("{:?}", e)),
     }
 
-    let config = Arc::new(AoConfig::new(mode).expect("Failed to read configuration"));
+    if config.db_warmup_connections > 0 {
+        let opened = data_store.warmup(config.db_warmup_connections);
+        logger.log(format!(
+            "opened {} new database connections warming up to {}, {} open",
+            opened,
+            config.db_warmup_connections,
+            data_store.pool_stats().connections
+        ));
+    }
+
+    if config.pool_stats_interval_secs > 0 {
+        let stats_store = data_store.clone();
+        let stats_logger = logger.clone();
+        let period = Duration::from_secs(config.pool_stats_interval_secs);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(period);
+            loop {
+                interval.tick().await;
+                let stats = stats_store.pool_stats();
+                stats_logger.log(format!(
+                    "db pool - max size: {}, connections: {}, idle: {}, acquires: {}, avg acquire: {}us, slow acquires: {}",
+                    stats.max_size,
+                    stats.connections,
+                    stats.idle_connections,
+                    stats.acquires,
+                    stats.avg_acquire_micros,
+                    stats.slow_acquires
+                ));
+            }
+        });
+    }
+
+    if config.maintenance_interval_secs > 0 {
+        let maintenance_store = data_store.clone();
+        let maintenance_logger = logger.clone();
+        let period = Duration::from_secs(config.maintenance_interval_secs);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(period);
+            // the first tick fires immediately, skip it so startup isn't slowed down
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                let store = maintenance_store.clone();
+                match tokio::task::spawn_blocking(move || store.run_maintenance()).await {
+                    Ok(Ok(m)) => maintenance_logger.log(format!("db maintenance - {}", m)),
+                    Ok(Err(e)) => maintenance_logger.log(format!("db maintenance - {:?}", e)),
+                    Err(e) => maintenance_logger.log(format!("db maintenance - {}", e)),
+                }
+            }
+        });
+    }
 
     let scheduler_deps = Arc::new(core::scheduler::SchedulerDeps {
         data_store: data_store.clone(),
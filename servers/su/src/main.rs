@@ -20,6 +20,16 @@ struct FromTo {
     limit: Option<i32>,
     #[serde(rename = "process-id")]
     process_id: Option<String>,
+    // include-total is either present or not, it has no value
+    #[serde(rename = "include-total")]
+    include_total: Option<String>,
+    // asc (the default) or desc for newest first
+    sort: Option<String>,
+    // true or false, default to an exclusive from and inclusive to
+    #[serde(rename = "from-inclusive")]
+    from_inclusive: Option<bool>,
+    #[serde(rename = "to-inclusive")]
+    to_inclusive: Option<bool>,
 }
 
 #[derive(Deserialize)]
@@ -160,6 +170,15 @@ async fn main_get_route(
     let to_sort_key = query_params.to.clone();
     let limit = query_params.limit.clone();
     let process_id = query_params.process_id.clone();
+    let include_total = query_params.include_total.is_some();
+    let range = match flows::message_range(
+        query_params.sort.clone(),
+        query_params.from_inclusive,
+        query_params.to_inclusive,
+    ) {
+        Ok(range) => range,
+        Err(err) => return err_response(err),
+    };
 
     match router::redirect_tx_id(deps.get_ref().clone(), tx_id.clone(), process_id.clone()).await {
         Ok(Some(redirect_url)) => {
@@ -178,6 +197,8 @@ async fn main_get_route(
         from_sort_key,
         to_sort_key,
         limit,
+        include_total,
+        range,
     )
     .await;
 
@@ -219,6 +240,18 @@ async fn health_check() -> impl Responder {
     HttpResponse::Ok()
 }
 
+async fn migration_status_route(deps: web::Data<Arc<Deps>>) -> impl Responder {
+    match flows::migration_status(deps.get_ref().clone()).await {
+        Ok((true, status_str)) => HttpResponse::Ok()
+            .content_type("application/json")
+            .body(status_str),
+        Ok((false, status_str)) => HttpResponse::ServiceUnavailable()
+            .content_type("application/json")
+            .body(status_str),
+        Err(err) => err_response(err.to_string()),
+    }
+}
+
 #[actix_web::main]
 async fn main() -> io::Result<()> {
     let args: Vec<String> = env::args().collect();
@@ -267,6 +300,7 @@ async fn main() -> io::Result<()> {
             .route("/", web::post().to(main_post_route))
             .route("/timestamp", web::get().to(timestamp_route))
             .route("/health", web::get().to(health_check))
+            .route("/migration-status", web::get().to(migration_status_route))
             .route("/{tx_id}", web::get().to(main_get_route))
             .route("/processes/{process_id}", web::get().to(read_process_route))
     })